@@ -2916,8 +2916,11 @@ LedgerManagerImpl::ApplyState::evictFromModuleCache(
         {
             CLOG_DEBUG(Ledger, "evicting {} from module cache", binToHex(hash));
             ::rust::Slice<uint8_t const> slice{hash.data(), hash.size()};
-            mModuleCache->evict_contract_code(slice);
-            getMetrics().mSorobanMetrics.mModuleCacheNumEntries.dec();
+            if (mModuleCache->evict_contract_code(slice,
+                                                  /*error_if_pinned=*/false))
+            {
+                getMetrics().mSorobanMetrics.mModuleCacheNumEntries.dec();
+            }
         }
     }
 }
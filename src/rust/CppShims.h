@@ -20,10 +20,20 @@ template <typename T> class Vec;
 }
 }
 struct CxxBuf;
+struct RustBuf;
 namespace stellar
 {
 class Application;
 
+// Receives the encoded diagnostic events of an invocation one at a time, see
+// `invoke_host_function_with_diagnostic_sink` in the rust bridge.
+class DiagnosticEventSink
+{
+  public:
+    virtual ~DiagnosticEventSink() = default;
+    virtual void push(RustBuf event) = 0;
+};
+
 inline bool
 shim_isLogLevelAtLeast(std::string const& partition, LogLevel level)
 {
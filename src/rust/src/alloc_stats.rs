@@ -0,0 +1,70 @@
+// Copyright 2025 Stellar Development Foundation and contributors. Licensed
+// under the Apache License, Version 2.0. See the COPYING file at the root
+// of this distribution or at http://www.apache.org/licenses/LICENSE-2.0
+
+// A global allocator that forwards to the system allocator while keeping a
+// count of the bytes currently allocated by rust code. This complements the
+// soroban budget's memory metering with what the process actually holds on
+// to, which is what matters when chasing memory growth in a long-running
+// node. Only C++-side allocations are invisible to it.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicI64 = AtomicI64::new(0);
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            LIVE_BYTES.fetch_add(layout.size() as i64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            LIVE_BYTES.fetch_add(layout.size() as i64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE_BYTES.fetch_sub(layout.size() as i64, Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            LIVE_BYTES.fetch_add(new_size as i64 - layout.size() as i64, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+// Returns the number of bytes currently allocated through the rust global
+// allocator, across all threads.
+pub(crate) fn live_bytes() -> i64 {
+    LIVE_BYTES.load(Ordering::Relaxed)
+}
+
+#[cfg(all(feature = "testutils", feature = "allocation-stats"))]
+#[test]
+fn live_bytes_follow_allocations() {
+    // Other tests allocate concurrently, so only check for a change far
+    // larger than they would make.
+    const SIZE: usize = 64 << 20;
+    let before = live_bytes();
+    let buf = std::hint::black_box(vec![1u8; SIZE]);
+    let held = live_bytes();
+    assert!(held - before >= (SIZE / 2) as i64);
+    drop(buf);
+    assert!(held - live_bytes() >= (SIZE / 2) as i64);
+}
@@ -1,6 +1,9 @@
 // The cxx::bridge attribute says that everything in mod rust_bridge is
 // interpreted by cxx.rs.
 #[cxx::bridge]
+// The Rust functions declared here take their arguments the way the C++ call
+// sites hold them: one parameter per input, with vectors passed as `&Vec`.
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
 pub(crate) mod rust_bridge {
     // When we want to pass owned data _from_ C++, we typically want to pass it
     // as a C++-allocated std::vector<uint8_t>, because that's most-compatible
@@ -27,6 +30,31 @@ pub(crate) mod rust_bridge {
         hash: String,
     }
 
+    // How an entry in `InvokeHostFunctionOutput::modified_ledger_entries`
+    // was changed by the invocation.
+    enum LedgerEntryChangeKind {
+        // The entry (or, for a TTL entry, the entry it belongs to) was not
+        // passed to the invocation as a live entry: it is new or was restored
+        // from the archive.
+        Created,
+        Updated,
+        // A TTL entry whose live until ledger was extended.
+        TtlBumped,
+    }
+
+    // Coarse category of the error an invocation failed with, so that it can
+    // be handled without matching on error types and codes, whose meaning
+    // varies across protocols.
+    enum HostErrorClass {
+        // The invocation succeeded.
+        None,
+        // The contract failed, or made the host fail, on its own terms.
+        ContractFault,
+        InternalFault,
+        BudgetExhausted,
+        MalformedInput,
+    }
+
     // Result of invoking a host function.
     // When `success` is `false`, the function has failed. The diagnostic events
     // and metering data will be populated, but result value and effects won't
@@ -39,19 +67,120 @@ pub(crate) mod rust_bridge {
         // something that should never happen, so it's important to be able
         // to act on them in Core.
         is_internal_error: bool,
+        // In case if `success` is `false`, the category of the error the
+        // invocation failed with.
+        error_class: HostErrorClass,
+        // In case if `success` is `false` indicates whether the invocation
+        // itself succeeded but its encoded outputs exceeded the
+        // `max_output_bytes` limit from the invocation options.
+        output_too_large: bool,
         // Diagnostic information concerning the host function execution.
         diagnostic_events: Vec<RustBuf>,
         cpu_insns: u64,
         mem_bytes: u64,
+        // Instructions the invocation attempted to consume. The soroban budget
+        // does not cap its counter: it includes the full cost of the charge
+        // that exceeded the limit, but nothing the contract would have done
+        // afterwards. So this is currently always equal to `cpu_insns`, and on
+        // budget exhaustion is a lower bound on what the contract needed.
+        attempted_cpu_insns: u64,
+        // The budget limits that `cpu_insns` and `mem_bytes` were metered
+        // against.
+        instruction_limit: u64,
+        memory_limit: u64,
         time_nsecs: u64,
         cpu_insns_excluding_vm_instantiation: u64,
         time_nsecs_excluding_vm_instantiation: u64,
+        // `ContractCostType` discriminant of the cost type that consumed the
+        // most cpu instructions.
+        dominant_cpu_cost_type: u32,
+        // `ContractCostType` discriminants, in ascending order, of the cost
+        // types that were charged any cpu or memory.
+        charged_cost_types: Vec<u32>,
+        // Protocol version (from the ledger info) the invocation was
+        // evaluated under.
+        protocol_version: u32,
 
-        // Effects of the invocation that are only populated in case of success.
+        // Effects of the invocation that are only populated in case of success
+        // (except `result_value`, see `include_contract_error_result`).
         result_value: RustBuf,
         contract_events: Vec<RustBuf>,
         modified_ledger_entries: Vec<RustBuf>,
+        // Parallel to `modified_ledger_entries`.
+        modified_ledger_entry_kinds: Vec<LedgerEntryChangeKind>,
+        // Encoded `LedgerKey`s of the entries the invocation deleted, which
+        // are otherwise only implied by their absence from
+        // `modified_ledger_entries`.
+        deleted_ledger_keys: Vec<RustBuf>,
+        // Whether the invocation neither created, updated nor deleted any
+        // entry, other than by extending TTLs. Only set on success.
+        read_only_execution: bool,
+        // Encoded `LedgerKey`s of all entries the invocation accessed, for
+        // reconciling against the declared footprint. Only populated on
+        // success when `include_touched_keys` is set in the invocation
+        // options.
+        touched_keys: Vec<RustBuf>,
         rent_fee: i64,
+        // Compact description of `result_value` (its type and, for
+        // containers, element count). Only populated on success when
+        // `include_result_summary` is set in the invocation options.
+        result_summary: String,
+        // See `capture_host_output` in the invocation options.
+        host_output: String,
+        // `contract_events` and `diagnostic_events` packed into one buffer
+        // each (see `pack_events` in the invocation options), in which case
+        // those two fields are left empty.
+        packed_contract_events: RustBuf,
+        packed_diagnostic_events: RustBuf,
+    }
+
+    // Optional behaviors of `invoke_host_function`. A default-initialized
+    // struct yields the standard behavior.
+    struct CxxInvokeHostFunctionOptions {
+        include_result_summary: bool,
+        // Upper bound on the total size in bytes of the encoded result value,
+        // contract events, modified ledger entries and diagnostic events
+        // returned on success. Exceeding it turns the invocation into a
+        // failure with `output_too_large` set. 0 means unbounded.
+        max_output_bytes: u64,
+        // Run with unbounded budget limits, for benchmarking the cost of
+        // metering. Costs are still tracked and reported. Only honored in
+        // builds with the `testutils` feature.
+        unmetered: bool,
+        // Leave out of `modified_ledger_entries` any written entry whose new
+        // value is identical to its original one. Such entries are then
+        // neither modified nor deleted, so callers must use
+        // `deleted_ledger_keys` to find deletions rather than relying on
+        // absence from `modified_ledger_entries`.
+        skip_unchanged_writes: bool,
+        // Reject the invocation before execution if `instruction_limit`
+        // exceeds this value; 0 disables the check.
+        max_allowed_instructions: u64,
+        // Populate `touched_keys` in the output.
+        include_touched_keys: bool,
+        // Fail the invocation with an internal error if it emits a contract
+        // event with more than this many topics; 0 disables the check.
+        max_event_topics: u32,
+        // Fail the invocation with an `ExceededLimit` events error if it
+        // emits more than this many contract events; 0 means unbounded.
+        max_contract_events: u32,
+        // From protocol 22, when the invocation fails with a contract error
+        // (which is not an internal error), return that error encoded as an
+        // `ScVal::Error` in `result_value`.
+        include_contract_error_result: bool,
+        // Test builds only: return the host's trace output (as with
+        // `is_tx_tracing_enabled`) and the debug form of its diagnostic
+        // events in `host_output`, instead of logging them.
+        capture_host_output: bool,
+        // Test builds only: if nonzero, run with this base reserve instead of
+        // `ledger_info.base_reserve`, for measuring how fees respond to it.
+        base_reserve_override: u32,
+        // Return `contract_events` and `diagnostic_events` as
+        // `packed_contract_events` and `packed_diagnostic_events` instead:
+        // the concatenation of each event prefixed with its length as an
+        // 8-byte big-endian integer. This saves passing every event over the
+        // bridge as a separate buffer.
+        pack_events: bool,
     }
 
     // LogLevel declares to cxx.rs a shared type that both Rust and C+++ will
@@ -96,6 +225,9 @@ pub(crate) mod rust_bridge {
         pub xdr_git_rev: String,
         pub xdr_base_git_rev: String,
         pub xdr_file_hashes: Vec<XDRFileHash>,
+        // SHA-256 digest summarizing all of `xdr_file_hashes`, for comparing
+        // XDR definitions in one step.
+        pub xdr_definitions_digest: Vec<u8>,
     }
 
     struct CxxTransactionResources {
@@ -108,6 +240,7 @@ pub(crate) mod rust_bridge {
         transaction_size_bytes: u32,
     }
 
+    #[derive(Clone)]
     struct CxxFeeConfiguration {
         fee_per_instruction_increment: i64,
         fee_per_disk_read_entry: i64,
@@ -128,7 +261,7 @@ pub(crate) mod rust_bridge {
         new_live_until_ledger: u32,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     struct CxxRentFeeConfiguration {
         fee_per_write_1kb: i64,
         fee_per_rent_1kb: i64,
@@ -137,6 +270,7 @@ pub(crate) mod rust_bridge {
         temporary_rent_rate_denominator: i64,
     }
 
+    #[derive(Clone)]
     struct CxxRentWriteFeeConfiguration {
         state_target_size_bytes: i64,
         rent_fee_1kb_state_size_low: i64,
@@ -155,6 +289,77 @@ pub(crate) mod rust_bridge {
         refundable_fee: i64,
     }
 
+    // A cpu and memory cost pair, as charged to a budget.
+    struct CpuMemCost {
+        cpu_insns: u64,
+        mem_bytes: u64,
+    }
+
+    // All the fee components of a transaction, see `compute_full_soroban_fee`.
+    struct SorobanFeeBreakdown {
+        non_refundable_fee: i64,
+        refundable_fee: i64,
+        rent_fee: i64,
+        write_fee_per_1kb: i64,
+    }
+
+    // The size of the authorization of a transaction, see
+    // `auth_complexity`.
+    struct AuthComplexity {
+        entry_count: u64,
+        // Depth of the deepest invocation tree; a root invocation without
+        // sub-invocations has depth 1.
+        max_nesting_depth: u32,
+    }
+
+    // The inputs of an `invoke_host_function` call, as captured by
+    // `serialize_invocation_inputs`. The ledger info fields are inlined
+    // since `CxxLedgerInfo` holds C++-owned buffers.
+    struct InvocationInputsBundle {
+        instruction_limit: u32,
+        hf: RustBuf,
+        resources: RustBuf,
+        restored_rw_entry_indices: Vec<u32>,
+        source_account: RustBuf,
+        auth_entries: Vec<RustBuf>,
+        protocol_version: u32,
+        sequence_number: u32,
+        timestamp: u64,
+        network_id: Vec<u8>,
+        base_reserve: u32,
+        memory_limit: u32,
+        min_temp_entry_ttl: u32,
+        min_persistent_entry_ttl: u32,
+        max_entry_ttl: u32,
+        cpu_cost_params: RustBuf,
+        mem_cost_params: RustBuf,
+        ledger_entries: Vec<RustBuf>,
+        ttl_entries: Vec<RustBuf>,
+        base_prng_seed: RustBuf,
+        rent_fee_configuration: CxxRentFeeConfiguration,
+    }
+
+    // Cumulative time spent invoking host functions on the soroban host whose
+    // max protocol is `protocol_version`, see `get_per_version_timings`.
+    struct VersionTiming {
+        protocol_version: u32,
+        cumulative_nanos: u64,
+    }
+
+    // The diagnostic events emitted by one contract, see
+    // `group_diagnostic_events_by_contract`.
+    struct DiagnosticEventGroup {
+        // 32-byte contract id; all zeros for events without one.
+        contract_id: Vec<u8>,
+        events: Vec<RustBuf>,
+    }
+
+    struct DecodedTtlEntry {
+        live_until_ledger_seq: u32,
+        // The 32-byte hash of the key of the entry this TTL applies to.
+        key_hash: Vec<u8>,
+    }
+
     // These are used as return code for the command line tool so we use a
     // higher value to avoid collision. Note rust bridge translates these into
     // uint8_t, so make sure the values <= 255.
@@ -194,9 +399,197 @@ pub(crate) mod rust_bridge {
             ttl_entries: &Vec<CxxBuf>,
             base_prng_seed: &CxxBuf,
             rent_fee_configuration: CxxRentFeeConfiguration,
+            options: &CxxInvokeHostFunctionOptions,
+            module_cache: &SorobanModuleCache,
+        ) -> Result<InvokeHostFunctionOutput>;
+
+        // Runs a host function like `invoke_host_function` with diagnostics
+        // enabled, but passes each encoded diagnostic event to `sink` as it's
+        // produced instead of returning them in `diagnostic_events`.
+        fn invoke_host_function_with_diagnostic_sink(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: CxxRentFeeConfiguration,
+            options: &CxxInvokeHostFunctionOptions,
             module_cache: &SorobanModuleCache,
+            sink: Pin<&mut DiagnosticEventSink>,
         ) -> Result<InvokeHostFunctionOutput>;
 
+        // Runs a host function like `invoke_host_function` with diagnostics
+        // forced on and returns its diagnostic events, for debugging a failed
+        // invocation that originally ran without diagnostics.
+        fn replay_with_diagnostics(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: CxxRentFeeConfiguration,
+            options: &CxxInvokeHostFunctionOptions,
+            module_cache: &SorobanModuleCache,
+        ) -> Result<Vec<RustBuf>>;
+
+        // Runs a host function like `invoke_host_function` but only returns
+        // the contract events it emitted, skipping the computation of ledger
+        // effects and rent fees. Fails if the invocation fails.
+        fn invoke_and_collect_events(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: &CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            module_cache: &SorobanModuleCache,
+        ) -> Result<Vec<RustBuf>>;
+
+        // Runs an invocation with diagnostics disabled and enabled and
+        // returns the difference in metered cpu instructions. Only available
+        // in builds with the `testutils` feature.
+        fn measure_diagnostics_overhead(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: &CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: &CxxRentFeeConfiguration,
+            options: &CxxInvokeHostFunctionOptions,
+            module_cache: &SorobanModuleCache,
+        ) -> Result<i64>;
+
+        // Runs an invocation and returns the net number of bytes the rust
+        // allocator retains afterwards (which the budget's memory metering
+        // does not see). Only available in builds with both the `testutils`
+        // and `allocation-stats` features.
+        fn measure_retained_allocation(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: &CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: &CxxRentFeeConfiguration,
+            options: &CxxInvokeHostFunctionOptions,
+            module_cache: &SorobanModuleCache,
+        ) -> Result<i64>;
+
+        // Decodes the inputs of an invocation and encodes a synthetic output
+        // without running the host, returning the nanoseconds spent. Only
+        // available in builds with the `testutils` feature.
+        fn measure_marshalling_overhead(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+        ) -> Result<u64>;
+
+        // Lists the field-level differences between the committed effects
+        // (success, result, modified entries, events and rent fee) of two
+        // invocations, ignoring metering and timing. Only available in builds
+        // with the `testutils` feature.
+        fn compare_invocation_outputs(
+            a: &InvokeHostFunctionOutput,
+            b: &InvokeHostFunctionOutput,
+        ) -> Result<Vec<String>>;
+
+        // Returns a 32-byte digest of the committed effects (result value,
+        // contract events in emitted order and modified entries in any order)
+        // of an invocation, so that two runs can be checked for agreement with
+        // a single comparison.
+        fn invocation_output_digest(output: &InvokeHostFunctionOutput) -> Result<RustBuf>;
+
+        // Returns the ratio of the time the cost model implies for `cpu_insns`
+        // at `insns_per_nsec` to the measured `time_nsecs` of an invocation,
+        // for detecting calibration drift. Only available in builds with the
+        // `testutils` feature.
+        fn modeled_to_measured_time_ratio(
+            cpu_insns: u64,
+            time_nsecs: u64,
+            insns_per_nsec: f64,
+        ) -> Result<f64>;
+
+        // Encodes a sample `DiagnosticEvent` shaped like the one emitted for
+        // a failed host function, with `topic` as its only topic and an
+        // optional 32-byte `contract_id` (empty for none). Only available in
+        // builds with the `testutils` feature.
+        fn make_test_diagnostic_event(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            topic: &str,
+            contract_id: &[u8],
+        ) -> Result<RustBuf>;
+
+        // Packages the inputs of an `invoke_host_function` call into a single
+        // self-describing buffer (an encoded `ScVal` map), so that a failing
+        // invocation can be replayed offline. Only available in builds with
+        // the `testutils` feature.
+        fn serialize_invocation_inputs(
+            config_max_protocol: u32,
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: &CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: &CxxRentFeeConfiguration,
+        ) -> Result<RustBuf>;
+
+        // Unpacks a buffer made by `serialize_invocation_inputs`. Only
+        // available in builds with the `testutils` feature.
+        fn deserialize_invocation_inputs(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            bundle: &CxxBuf,
+        ) -> Result<InvocationInputsBundle>;
+
+        // Returns a readable summary of an encoded
+        // `SorobanAuthorizationEntry`: its credentials, signature count and
+        // root invocation. Only available in builds with the `testutils`
+        // feature.
+        fn summarize_auth_entry(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            auth_entry: &CxxBuf,
+        ) -> Result<String>;
+
         fn init_logging(maxLevel: LogLevel) -> Result<()>;
 
         // Accessors for test wasms, compiled into soroban-test-wasms crate.
@@ -226,6 +619,9 @@ pub(crate) mod rust_bridge {
         // Return the rustc version used to build this binary.
         fn get_rustc_version() -> String;
 
+        // Return the names of the cargo features this binary was built with.
+        fn get_build_features() -> Vec<String>;
+
         // Exposes Rust's platform-compatible method for getting the full
         // filesystem path of the current running executable.
         fn current_exe() -> Result<String>;
@@ -234,9 +630,36 @@ pub(crate) mod rust_bridge {
         // if the protocol version is not supported.
         fn get_soroban_version_info(core_max_proto: u32) -> Vec<SorobanVersionInfo>;
 
+        // Return the version info of each soroban linked into this binary,
+        // without checking them against the core protocol version.
+        fn list_linked_soroban_versions() -> Vec<SorobanVersionInfo>;
+
+        // Run a trivial contract invocation on each linked soroban, to prime
+        // lazily-initialized state before real transactions arrive. Meant to
+        // be called once at startup.
+        fn warmup() -> Result<()>;
+
+        // Return the cumulative wall-clock time spent in `invoke_host_function`
+        // on each linked soroban, in ascending protocol order. Empty unless
+        // built with the "version-timings" feature.
+        fn get_per_version_timings() -> Vec<VersionTiming>;
+
+        // Return the lowest ledger protocol version any soroban linked into
+        // this binary can execute.
+        fn get_min_proto() -> u32;
+
+        // Fails, naming the supported range, unless some soroban linked into
+        // this binary can execute ledgers of `protocol_version`.
+        fn can_apply_ledger(protocol_version: u32) -> Result<()>;
+
         // Check to see if the XDR files used by different rust dependencies match.
         fn check_xdr_version_identities() -> Result<()>;
 
+        // Fails, naming the offending field, if any rate of `config` is
+        // negative. Rates have no upper bound, matching the network config
+        // validation. The fee computations below apply the same check.
+        fn validate_fee_configuration(config: &CxxFeeConfiguration) -> Result<()>;
+
         // Computes the resource fee given the transaction resource consumption
         // and network configuration.
         fn compute_transaction_resource_fee(
@@ -246,6 +669,91 @@ pub(crate) mod rust_bridge {
             fee_config: CxxFeeConfiguration,
         ) -> Result<FeePair>;
 
+        // Computes the resource fee of both `base` and `adjusted` resources
+        // under the same network configuration, and returns the
+        // component-wise difference (adjusted minus base).
+        fn compute_resource_fee_delta(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            base: CxxTransactionResources,
+            adjusted: CxxTransactionResources,
+            fee_config: CxxFeeConfiguration,
+        ) -> Result<FeePair>;
+
+        // Computes an upper bound of the fee of a transaction with the given
+        // `SorobanResources`, assuming all its read-write entries are newly
+        // written at `write_bytes` each and live for `max_entry_ttl` (the
+        // network setting) ledgers. Fees for events and transaction size are
+        // not included, since the resources don't determine them.
+        fn estimate_worst_case_fee(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            resources_buf: &CxxBuf,
+            fee_config: CxxFeeConfiguration,
+            rent_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+            max_entry_ttl: u32,
+        ) -> Result<FeePair>;
+
+        // Computes the resource fee, rent fee and rent write fee per 1kb of a
+        // transaction in one call, deriving the fee rates that depend on the
+        // bucket list size from `bucket_list_size` instead of taking them from
+        // `fee_config` and `rent_fee_config`.
+        fn compute_full_soroban_fee(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            tx_resources: CxxTransactionResources,
+            fee_config: CxxFeeConfiguration,
+            changed_entries: &Vec<CxxLedgerEntryRentChange>,
+            rent_fee_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+            bucket_list_size: i64,
+            rent_write_fee_config: CxxRentWriteFeeConfiguration,
+        ) -> Result<SorobanFeeBreakdown>;
+
+        // Computes the fee of a RestoreFootprint operation restoring the
+        // encoded persistent contract `entries`: the write fee for the entries
+        // plus their rent fee, deriving the rent rate from `bucket_list_size`
+        // like `compute_full_soroban_fee` does.
+        fn compute_restore_footprint_fee(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            entries: &Vec<CxxBuf>,
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+            bucket_list_size: i64,
+            write_config: CxxRentWriteFeeConfiguration,
+            rent_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+            min_persistent_ttl: u32,
+        ) -> Result<i64>;
+
+        // Fails if the refundable resource fee and rent fee an invocation
+        // consumed don't fit in the refundable fee the transaction declared.
+        fn check_refundable_allowance(
+            computed_refundable: i64,
+            computed_rent: i64,
+            declared_allowance: i64,
+        ) -> Result<()>;
+
+        // Computes the rent fee of extending every contract entry of the
+        // read-write footprint of a `SorobanResources` to the maximum TTL
+        // `max_ttl`. `ledger_entries` and `ttl_entries` hold the current
+        // entries, paired as for `invoke_host_function`. Entries are sized
+        // for rent like `ledger_entry_rent_bytes` does.
+        fn estimate_max_ttl_bump_fee(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            resources_buf: &CxxBuf,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            fee_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+            max_ttl: u32,
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+        ) -> Result<i64>;
+
         // Computes the write fee per 1kb written to the ledger given the
         // current bucket list size and network configuration.
         fn compute_rent_write_fee_per_1kb(
@@ -255,6 +763,15 @@ pub(crate) mod rust_bridge {
             fee_config: CxxRentWriteFeeConfiguration,
         ) -> Result<i64>;
 
+        // Evaluates `compute_rent_write_fee_per_1kb` at each of the given
+        // bucket list sizes, returning the fees in the same order.
+        fn estimate_rent_fee_curve(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            sizes: &Vec<i64>,
+            fee_config: CxxRentWriteFeeConfiguration,
+        ) -> Result<Vec<i64>>;
+
         // Computes the rent fee given the ledger entry changes and network
         // configuration.
         fn compute_rent_fee(
@@ -265,6 +782,47 @@ pub(crate) mod rust_bridge {
             current_ledger_seq: u32,
         ) -> Result<i64>;
 
+        // Computes the rent fee of each of the given ledger entry changes on
+        // its own, in input order. The fees add up to at least the
+        // `compute_rent_fee` of all the changes together: the cost of writing
+        // TTL extensions is rounded up per entry rather than once overall.
+        fn compute_rent_fee_itemized(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            changed_entries: &Vec<CxxLedgerEntryRentChange>,
+            fee_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+        ) -> Result<Vec<i64>>;
+
+        // Computes the rent fee like `compute_rent_fee`, but with separate
+        // configurations for temporary and persistent entries. Returns the sum
+        // of the two fees.
+        fn compute_rent_fee_split(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            temp_entries: &Vec<CxxLedgerEntryRentChange>,
+            temp_fee_config: CxxRentFeeConfiguration,
+            persistent_entries: &Vec<CxxLedgerEntryRentChange>,
+            persistent_fee_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+        ) -> Result<i64>;
+
+        // Returns the furthest live-until ledger a (non-code) entry of the
+        // given size, expiring at `current_ledger_seq`, can be extended to
+        // for at most `fee_budget` in rent. The result is capped by the
+        // network's `max_entry_ttl`, and is `current_ledger_seq` if no
+        // extension is affordable.
+        fn compute_max_ttl_extension(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            entry_size_bytes: u32,
+            is_persistent: bool,
+            fee_budget: i64,
+            fee_config: CxxRentFeeConfiguration,
+            current_ledger_seq: u32,
+            max_entry_ttl: u32,
+        ) -> Result<u32>;
+
         // Computes in-memory size of the ContractCodeEntry used for the rent
         // fee computation.
         // In-memory size is only used for contract code starting from protocol
@@ -277,6 +835,90 @@ pub(crate) mod rust_bridge {
             mem_cost_params: &CxxBuf,
         ) -> Result<u32>;
 
+        // Returns the names of the optional wasm proposals (e.g.
+        // "bulk-memory") that the host for `protocol_version` accepts in
+        // contracts.
+        fn get_supported_wasm_features(
+            config_max_protocol: u32,
+            protocol_version: u32,
+        ) -> Result<Vec<String>>;
+
+        // Returns one line per `ContractCostType` of the protocol with its
+        // cpu and memory cost params, for auditing the active cost model.
+        fn describe_cost_model(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+        ) -> Result<Vec<String>>;
+
+        // Lists the cost types whose terms differ between two encoded
+        // `ContractCostParams`, as "Name: const a->b, linear c->d" (or as
+        // added/removed entries), for reviewing cost model upgrades. Only
+        // available in builds with the `testutils` feature.
+        fn diff_contract_cost_params(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            old: &CxxBuf,
+            new: &CxxBuf,
+        ) -> Result<Vec<String>>;
+
+        // Returns the size of an encoded LedgerEntry used for the rent fee
+        // computation: its encoded size, plus the in-memory size of the
+        // module for ContractCodeEntry starting from protocol 23.
+        fn ledger_entry_rent_bytes(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            entry: &CxxBuf,
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+        ) -> Result<u32>;
+
+        // Returns the cpu and memory cost of instantiating the module of the
+        // ContractCodeEntry, i.e. what a module cache hit saves an
+        // invocation.
+        fn vm_instantiation_cost(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            contract_code_entry: &CxxBuf,
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+        ) -> Result<CpuMemCost>;
+
+        // Returns the total size in bytes of the buffers passed to
+        // `invoke_host_function`.
+        fn invocation_input_size(
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+        ) -> usize;
+
+        // Returns a SHA-256 fingerprint of all the inputs that determine the
+        // outcome of an invocation, for keying caches of identical requests.
+        // Does not cover `enable_diagnostics` or the invocation options.
+        fn invocation_input_fingerprint(
+            instruction_limit: u32,
+            hf_buf: &CxxBuf,
+            resources: &CxxBuf,
+            restored_rw_entry_indices: &Vec<u32>,
+            source_account: &CxxBuf,
+            auth_entries: &Vec<CxxBuf>,
+            ledger_info: &CxxLedgerInfo,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+            base_prng_seed: &CxxBuf,
+            rent_fee_configuration: &CxxRentFeeConfiguration,
+        ) -> Vec<u8>;
+
+        // Checks that every ledger info has a 32-byte network ID, and that they
+        // all have the same one. Fails naming the index of the first
+        // offending ledger info otherwise.
+        fn validate_consistent_network_ids(infos: &Vec<CxxLedgerInfo>) -> Result<()>;
+
         // Checks if a provided `TransactionEnvelope` XDR can be parsed in the
         // provided `protocol_version`.
         fn can_parse_transaction(
@@ -286,6 +928,130 @@ pub(crate) mod rust_bridge {
             depth_limit: u32,
         ) -> Result<bool>;
 
+        // Like `can_parse_transaction` for a batch of envelopes, returning
+        // one result per envelope in input order. Large batches are parsed
+        // on multiple threads.
+        fn can_parse_transactions(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            xdrs: &Vec<CxxBuf>,
+            depth_limit: u32,
+        ) -> Result<Vec<bool>>;
+
+        // Returns whether a `TransactionEnvelope` XDR contains any Soroban
+        // operation (`InvokeHostFunction`, `ExtendFootprintTtl` or
+        // `RestoreFootprint`). Fails if it can't be parsed in the provided
+        // `protocol_version`.
+        fn is_soroban_transaction(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            xdr: &CxxBuf,
+            depth_limit: u32,
+        ) -> Result<bool>;
+
+        // Returns the size in bytes of an encoded `HostFunction`, after
+        // checking that it decodes in the provided `protocol_version`. Fails
+        // if it does not.
+        fn host_function_encoded_size(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            hf_buf: &CxxBuf,
+        ) -> Result<usize>;
+
+        // Returns the `HostFunctionType` discriminant of an encoded
+        // `HostFunction`, decoding only as much of it as needed. Fails if the
+        // type is not known in the provided `protocol_version`.
+        fn host_function_type(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            hf_buf: &CxxBuf,
+        ) -> Result<u32>;
+
+        // Decodes and validates an encoded `TtlEntry`.
+        fn decode_ttl_entry(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            buf: &CxxBuf,
+        ) -> Result<DecodedTtlEntry>;
+
+        // Decodes an encoded invocation source account and returns it in
+        // strkey form. Fails if it is not a valid `AccountId`.
+        fn decode_source_account(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            source_account: &CxxBuf,
+        ) -> Result<String>;
+
+        // Fails, naming the key, if any key of the footprint of an encoded
+        // `SorobanResources` is both read-only and read-write.
+        fn validate_footprint_disjoint(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            resources: &CxxBuf,
+        ) -> Result<()>;
+
+        // Checks that each non-empty entry of `ttl_entries` is the TTL entry
+        // of the ledger entry at the same index of `ledger_entries`, as
+        // `invoke_host_function` expects.
+        fn validate_ttl_entry_correspondence(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            ledger_entries: &Vec<CxxBuf>,
+            ttl_entries: &Vec<CxxBuf>,
+        ) -> Result<()>;
+
+        // Decodes each `SorobanAuthorizationEntry` within `depth_limit` and
+        // returns their count and the nesting depth of the deepest
+        // invocation tree, so that overly complex authorization can be
+        // rejected before invoking the host.
+        fn auth_complexity(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            auth_entries: &Vec<CxxBuf>,
+            depth_limit: u32,
+        ) -> Result<AuthComplexity>;
+
+        // Checks that the wasm of an encoded `ContractCodeEntry` hashes to
+        // the entry's hash, and returns that 32-byte hash. Fails if they
+        // have diverged.
+        fn validate_contract_code_entry(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            contract_code_entry: &CxxBuf,
+        ) -> Result<RustBuf>;
+
+        // Returns the hashes of the contract code a transaction is going to
+        // need, decoded from its footprint and from any contract instances
+        // among its ledger entries. Each returned buffer is a 32-byte key
+        // suitable for passing to `SorobanModuleCache::contains_module` or for
+        // looking up the wasm to `compile`, so the module cache can be warmed
+        // before invocation.
+        fn extract_referenced_code_hashes(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            resources: &CxxBuf,
+            ledger_entries: &Vec<CxxBuf>,
+        ) -> Result<Vec<RustBuf>>;
+
+        // Groups encoded diagnostic events by the contract that emitted them,
+        // in the order each contract first appears. Events without a
+        // contract id are grouped under an all-zero id.
+        fn group_diagnostic_events_by_contract(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            events: &Vec<RustBuf>,
+        ) -> Result<Vec<DiagnosticEventGroup>>;
+
+        // Returns the 32-byte id of the contract an encoded `HostFunction`
+        // invokes, or an empty buffer for functions that upload wasm or
+        // create a contract, so transactions can be tagged by their target
+        // contract without running them.
+        fn invoked_contract_address(
+            config_max_protocol: u32,
+            protocol_version: u32,
+            hf: &CxxBuf,
+        ) -> Result<RustBuf>;
+
         fn i128_add(lhs: &CxxI128, rhs: &CxxI128) -> Result<CxxI128>;
 
         fn i128_sub(lhs: &CxxI128, rhs: &CxxI128) -> Result<CxxI128>;
@@ -306,11 +1072,81 @@ pub(crate) mod rust_bridge {
             ledger_protocol: u32,
             source: &[u8],
         ) -> Result<()>;
+        // Same as `compile`, but returns the number of nanoseconds the
+        // compilation took.
+        fn compile_timed(
+            self: &mut SorobanModuleCache,
+            ledger_protocol: u32,
+            source: &[u8],
+        ) -> Result<u64>;
+        // Checks that a wasm compiles under the given protocol, returning the
+        // compilation error if not. Unlike `compile`, this leaves the cache
+        // unchanged.
+        fn validate_wasm(
+            self: &SorobanModuleCache,
+            ledger_protocol: u32,
+            source: &[u8],
+        ) -> Result<()>;
+        // Compiles a wasm like `validate_wasm` and returns the names of the
+        // functions it exports, e.g. for rejecting uploads that export none.
+        fn inspect_wasm_exports(
+            self: &SorobanModuleCache,
+            ledger_protocol: u32,
+            source: &[u8],
+        ) -> Result<Vec<String>>;
+        // Returns whether a wasm is small enough to be worth compiling under
+        // the given protocol, i.e. does not exceed `max_contract_size_bytes`
+        // (the network's contract size limit). Returns false without
+        // compiling anything.
+        fn wasm_within_size_limit(
+            self: &SorobanModuleCache,
+            ledger_protocol: u32,
+            source: &[u8],
+            max_contract_size_bytes: u32,
+        ) -> Result<bool>;
+        // Returns an approximation of the memory compiling a wasm would
+        // consume under the given protocol and network cost parameters,
+        // derived from its size without compiling it.
+        fn estimate_compile_memory(
+            self: &SorobanModuleCache,
+            ledger_protocol: u32,
+            source: &[u8],
+            cpu_cost_params: &CxxBuf,
+            mem_cost_params: &CxxBuf,
+        ) -> Result<u64>;
         fn shallow_clone(self: &SorobanModuleCache) -> Result<Box<SorobanModuleCache>>;
-        fn evict_contract_code(self: &mut SorobanModuleCache, key: &[u8]) -> Result<()>;
+        // Evicts the module for `key` and returns whether it was cached.
+        // Pinned modules are not evicted: this either fails or does nothing
+        // (returning false), depending on `error_if_pinned`.
+        fn evict_contract_code(
+            self: &mut SorobanModuleCache,
+            key: &[u8],
+            error_if_pinned: bool,
+        ) -> Result<bool>;
+        // Evicts all modules that are not pinned.
         fn clear(self: &mut SorobanModuleCache) -> Result<()>;
+        // Pins (or unpins) the module for `key`, so that `clear` and
+        // `evict_contract_code` leave it in the cache. A key can be pinned
+        // before its module is compiled.
+        fn pin_module(self: &mut SorobanModuleCache, key: &[u8]) -> Result<()>;
+        fn unpin_module(self: &mut SorobanModuleCache, key: &[u8]) -> Result<()>;
         fn contains_module(self: &SorobanModuleCache, protocol: u32, key: &[u8]) -> Result<bool>;
+        // Returns the average nanoseconds `contains_module` takes for `key`
+        // over `iterations` lookups. Only available in builds with the
+        // `testutils` feature.
+        fn benchmark_cache_lookup(
+            self: &SorobanModuleCache,
+            key: &[u8],
+            iterations: u32,
+        ) -> Result<u64>;
         fn get_mem_bytes_consumed(self: &SorobanModuleCache) -> Result<u64>;
+        // Returns the ledger protocol version the module for `key` was
+        // compiled for (the one passed to `compile`), or 0 if it is not
+        // cached.
+        fn module_protocol_version(self: &SorobanModuleCache, key: &[u8]) -> Result<u32>;
+        // Returns the 32-byte keys of all currently-cached modules, e.g. to be
+        // persisted and recompiled (from the ledger's wasm) after a restart.
+        fn export_cached_keys(self: &SorobanModuleCache) -> Result<Vec<RustBuf>>;
 
         // Given a quorum set configuration, checks if quorum intersection is
         // enjoyed among all possible quorums. Returns `Ok(status)` where
@@ -367,6 +1203,12 @@ pub(crate) mod rust_bridge {
             level: LogLevel,
             msg: &CxxString,
         ) -> Result<()>;
+
+        // Receives encoded diagnostic events one at a time from
+        // `invoke_host_function_with_diagnostic_sink`.
+        type DiagnosticEventSink;
+        #[cfg(not(test))]
+        fn push(self: Pin<&mut DiagnosticEventSink>, event: RustBuf);
     }
 }
 
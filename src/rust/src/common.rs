@@ -43,6 +43,29 @@ pub(crate) fn get_rustc_version() -> String {
     rustc_simple_version::RUSTC_VERSION.to_string()
 }
 
+pub(crate) fn get_build_features() -> Vec<String> {
+    let mut features = vec![];
+    if cfg!(feature = "unified") {
+        features.push("unified".to_string());
+    }
+    if cfg!(feature = "tracy") {
+        features.push("tracy".to_string());
+    }
+    if cfg!(feature = "next") {
+        features.push("next".to_string());
+    }
+    if cfg!(feature = "testutils") {
+        features.push("testutils".to_string());
+    }
+    if cfg!(feature = "version-timings") {
+        features.push("version-timings".to_string());
+    }
+    if cfg!(feature = "allocation-stats") {
+        features.push("allocation-stats".to_string());
+    }
+    features
+}
+
 pub(crate) fn current_exe() -> Result<String, Box<dyn std::error::Error>> {
     std::env::current_exe()?
         .into_os_string()
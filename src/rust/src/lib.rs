@@ -39,10 +39,14 @@ mod soroban_test_wasm;
 #[cfg(feature = "testutils")]
 mod soroban_test_extra_protocol;
 
+#[cfg(feature = "allocation-stats")]
+mod alloc_stats;
+
 use soroban_module_cache::SorobanModuleCache;
 
 mod bridge;
 use bridge::rust_bridge;
+use rust_bridge::AuthComplexity;
 use rust_bridge::BridgeError;
 use rust_bridge::CxxBuf;
 use rust_bridge::CxxFeeConfiguration;
@@ -51,9 +55,15 @@ use rust_bridge::CxxLedgerInfo;
 use rust_bridge::CxxRentFeeConfiguration;
 
 use rust_bridge::CxxI128;
+use rust_bridge::CxxInvokeHostFunctionOptions;
 use rust_bridge::CxxRentWriteFeeConfiguration;
 use rust_bridge::CxxTransactionResources;
+use rust_bridge::DecodedTtlEntry;
+use rust_bridge::DiagnosticEventGroup;
 use rust_bridge::FeePair;
+use rust_bridge::InvocationInputsBundle;
 use rust_bridge::InvokeHostFunctionOutput;
 use rust_bridge::RustBuf;
+use rust_bridge::SorobanFeeBreakdown;
 use rust_bridge::SorobanVersionInfo;
+use rust_bridge::VersionTiming;
@@ -1,8 +1,14 @@
+use crate::rust_bridge::DiagnosticEventSink;
 use crate::{
-    soroban_proto_all::get_host_module_for_protocol, CxxBuf, CxxFeeConfiguration,
+    soroban_proto_all::{get_host_module_for_protocol, protocol_agnostic},
+    AuthComplexity, CxxBuf, CxxFeeConfiguration, CxxInvokeHostFunctionOptions,
     CxxLedgerEntryRentChange, CxxLedgerInfo, CxxRentFeeConfiguration, CxxRentWriteFeeConfiguration,
-    CxxTransactionResources, FeePair, InvokeHostFunctionOutput, SorobanModuleCache,
+    CxxTransactionResources, DecodedTtlEntry, DiagnosticEventGroup, FeePair,
+    InvocationInputsBundle, InvokeHostFunctionOutput, RustBuf, SorobanFeeBreakdown,
+    SorobanModuleCache,
 };
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
 
 pub(crate) fn invoke_host_function(
     config_max_protocol: u32,
@@ -10,17 +16,24 @@ pub(crate) fn invoke_host_function(
     instruction_limit: u32,
     hf_buf: &CxxBuf,
     resources_buf: CxxBuf,
-    restored_rw_entry_indices: &Vec<u32>,
+    restored_rw_entry_indices: &[u32],
     source_account_buf: &CxxBuf,
-    auth_entries: &Vec<CxxBuf>,
+    auth_entries: &[CxxBuf],
     ledger_info: CxxLedgerInfo,
-    ledger_entries: &Vec<CxxBuf>,
-    ttl_entries: &Vec<CxxBuf>,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
     base_prng_seed: &CxxBuf,
     rent_fee_configuration: CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
     module_cache: &SorobanModuleCache,
 ) -> Result<InvokeHostFunctionOutput, Box<dyn std::error::Error>> {
-    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    let protocol_version = ledger_info.protocol_version;
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version).map_err(|e| {
+        format!(
+            "no linked soroban host for protocol {} (configured max {}): {}",
+            protocol_version, config_max_protocol, e
+        )
+    })?;
     let res = (hm.invoke_host_function)(
         enable_diagnostics,
         instruction_limit,
@@ -34,6 +47,7 @@ pub(crate) fn invoke_host_function(
         ttl_entries,
         base_prng_seed,
         &rent_fee_configuration,
+        options,
         module_cache,
     );
 
@@ -54,18 +68,638 @@ pub(crate) fn invoke_host_function(
         ttl_entries,
         base_prng_seed,
         rent_fee_configuration,
+        options,
         module_cache,
     );
 
     res
 }
 
+// Runs an invocation with diagnostics enabled, passing each encoded diagnostic
+// event to the C++ `sink` as it's produced rather than returning them all in
+// the output, so that memory doesn't grow with the number of events.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn invoke_host_function_with_diagnostic_sink(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+    mut sink: Pin<&mut DiagnosticEventSink>,
+) -> Result<InvokeHostFunctionOutput, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    (hm.invoke_host_function_with_diagnostic_sink)(
+        instruction_limit,
+        hf_buf,
+        &resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        &ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        &rent_fee_configuration,
+        options,
+        module_cache,
+        &mut |event| sink.as_mut().push(event),
+    )
+}
+
+// The rust test runner doesn't link the C++ side of the bridge.
+#[cfg(test)]
+impl DiagnosticEventSink {
+    fn push(self: Pin<&mut Self>, _event: RustBuf) {}
+}
+
+// Re-runs an invocation with diagnostics enabled and returns its diagnostic
+// events, for explaining after the fact why a transaction applied without
+// diagnostics failed. The inputs have to be those of the original invocation
+// for the replay to be faithful.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn replay_with_diagnostics(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+) -> Result<Vec<RustBuf>, Box<dyn std::error::Error>> {
+    let output = invoke_host_function(
+        config_max_protocol,
+        true,
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        rent_fee_configuration,
+        options,
+        module_cache,
+    )?;
+    Ok(output.diagnostic_events)
+}
+
+// Runs the same invocation with diagnostics disabled and then enabled, and
+// returns how many more cpu instructions the latter was metered for, i.e. the
+// cost of generating diagnostic events for it.
+#[cfg(feature = "testutils")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_diagnostics_overhead(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    let invoke = |enable_diagnostics: bool| {
+        (hm.invoke_host_function)(
+            enable_diagnostics,
+            instruction_limit,
+            hf_buf,
+            resources_buf,
+            restored_rw_entry_indices,
+            source_account_buf,
+            auth_entries,
+            ledger_info,
+            ledger_entries,
+            ttl_entries,
+            base_prng_seed,
+            rent_fee_configuration,
+            options,
+            module_cache,
+        )
+    };
+    let without = invoke(false)?;
+    let with = invoke(true)?;
+    Ok(with.cpu_insns as i64 - without.cpu_insns as i64)
+}
+
+#[cfg(not(feature = "testutils"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_diagnostics_overhead(
+    _config_max_protocol: u32,
+    _instruction_limit: u32,
+    _hf_buf: &CxxBuf,
+    _resources_buf: &CxxBuf,
+    _restored_rw_entry_indices: &[u32],
+    _source_account_buf: &CxxBuf,
+    _auth_entries: &[CxxBuf],
+    _ledger_info: &CxxLedgerInfo,
+    _ledger_entries: &[CxxBuf],
+    _ttl_entries: &[CxxBuf],
+    _base_prng_seed: &CxxBuf,
+    _rent_fee_configuration: &CxxRentFeeConfiguration,
+    _options: &CxxInvokeHostFunctionOptions,
+    _module_cache: &SorobanModuleCache,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    Err("measure_diagnostics_overhead is only available in testutils builds".into())
+}
+
+// Runs an invocation and returns how many more bytes the rust allocator holds
+// afterwards than before, with the output already dropped. Allocations made by
+// other threads meanwhile are counted too, so this is only meaningful on an
+// otherwise idle process.
+#[cfg(all(feature = "testutils", feature = "allocation-stats"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_retained_allocation(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    let before = crate::alloc_stats::live_bytes();
+    let output = (hm.invoke_host_function)(
+        false,
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        rent_fee_configuration,
+        options,
+        module_cache,
+    );
+    drop(output);
+    Ok(crate::alloc_stats::live_bytes() - before)
+}
+
+#[cfg(not(all(feature = "testutils", feature = "allocation-stats")))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_retained_allocation(
+    _config_max_protocol: u32,
+    _instruction_limit: u32,
+    _hf_buf: &CxxBuf,
+    _resources_buf: &CxxBuf,
+    _restored_rw_entry_indices: &[u32],
+    _source_account_buf: &CxxBuf,
+    _auth_entries: &[CxxBuf],
+    _ledger_info: &CxxLedgerInfo,
+    _ledger_entries: &[CxxBuf],
+    _ttl_entries: &[CxxBuf],
+    _base_prng_seed: &CxxBuf,
+    _rent_fee_configuration: &CxxRentFeeConfiguration,
+    _options: &CxxInvokeHostFunctionOptions,
+    _module_cache: &SorobanModuleCache,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    Err(
+        "measure_retained_allocation is only available in testutils builds with the \
+         allocation-stats feature"
+            .into(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn invoke_and_collect_events(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    module_cache: &SorobanModuleCache,
+) -> Result<Vec<RustBuf>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    (hm.invoke_and_collect_events)(
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        module_cache,
+    )
+}
+
+#[cfg(feature = "testutils")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_marshalling_overhead(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.measure_marshalling_overhead)(
+        hf_buf,
+        resources_buf,
+        source_account_buf,
+        auth_entries,
+        ledger_entries,
+        ttl_entries,
+    )
+}
+
+#[cfg(not(feature = "testutils"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn measure_marshalling_overhead(
+    _config_max_protocol: u32,
+    _protocol_version: u32,
+    _hf_buf: &CxxBuf,
+    _resources_buf: &CxxBuf,
+    _source_account_buf: &CxxBuf,
+    _auth_entries: &[CxxBuf],
+    _ledger_entries: &[CxxBuf],
+    _ttl_entries: &[CxxBuf],
+) -> Result<u64, Box<dyn std::error::Error>> {
+    Err("measure_marshalling_overhead is only available in testutils builds".into())
+}
+
+// The encoded contract events of `output`, whether they were returned as
+// separate buffers or packed into one (see `pack_events`).
+fn output_contract_events(
+    output: &InvokeHostFunctionOutput,
+) -> Result<Vec<&[u8]>, Box<dyn std::error::Error>> {
+    if output.contract_events.is_empty() {
+        protocol_agnostic::unpack_buffers(&output.packed_contract_events.data)
+    } else {
+        Ok(output
+            .contract_events
+            .iter()
+            .map(|buf| buf.data.as_slice())
+            .collect())
+    }
+}
+
+// Lists the differences between the committed effects of two invocations,
+// e.g. the same transaction run under two protocols. Entries and events are
+// compared as sorted multisets of their encodings, events being unpacked
+// first if `pack_events` was set; metering and timing fields, which
+// legitimately differ, are ignored.
+#[cfg(feature = "testutils")]
+pub(crate) fn compare_invocation_outputs(
+    a: &InvokeHostFunctionOutput,
+    b: &InvokeHostFunctionOutput,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    fn sorted(mut bufs: Vec<&[u8]>) -> Vec<&[u8]> {
+        bufs.sort();
+        bufs
+    }
+    fn entries(output: &InvokeHostFunctionOutput) -> Vec<&[u8]> {
+        output
+            .modified_ledger_entries
+            .iter()
+            .map(|buf| buf.data.as_slice())
+            .collect()
+    }
+    let mut diffs = Vec::new();
+    if a.success != b.success {
+        diffs.push(format!("success: {} vs {}", a.success, b.success));
+    }
+    if a.result_value.data != b.result_value.data {
+        diffs.push(format!(
+            "result_value: {} bytes vs {} bytes",
+            a.result_value.data.len(),
+            b.result_value.data.len()
+        ));
+    }
+    for (name, a_bufs, b_bufs) in [
+        ("modified_ledger_entries", entries(a), entries(b)),
+        (
+            "contract_events",
+            output_contract_events(a)?,
+            output_contract_events(b)?,
+        ),
+    ] {
+        let (a_sorted, b_sorted) = (sorted(a_bufs), sorted(b_bufs));
+        if a_sorted != b_sorted {
+            let only_a = a_sorted
+                .iter()
+                .filter(|buf| !b_sorted.contains(buf))
+                .count();
+            let only_b = b_sorted
+                .iter()
+                .filter(|buf| !a_sorted.contains(buf))
+                .count();
+            diffs.push(format!(
+                "{}: {} vs {} ({} only in first, {} only in second)",
+                name,
+                a_sorted.len(),
+                b_sorted.len(),
+                only_a,
+                only_b
+            ));
+        }
+    }
+    if a.rent_fee != b.rent_fee {
+        diffs.push(format!("rent_fee: {} vs {}", a.rent_fee, b.rent_fee));
+    }
+    Ok(diffs)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn compare_invocation_outputs(
+    _a: &InvokeHostFunctionOutput,
+    _b: &InvokeHostFunctionOutput,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Err("compare_invocation_outputs is only available in testutils builds".into())
+}
+
+// Hashes the committed effects of an invocation: its result value followed by
+// its contract events, in the order they were emitted, and its modified ledger
+// entries, sorted so that the digest does not depend on the order the host
+// produced them in. Every buffer is prefixed with its length so that different
+// splits of the same bytes can't collide, and so that packed events (see
+// `pack_events`) hash like unpacked ones. Diagnostic events, metering and
+// timing are left out.
+pub(crate) fn invocation_output_digest(
+    output: &InvokeHostFunctionOutput,
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    Ok(committed_effects_digest(
+        &output.result_value.data,
+        output_contract_events(output)?,
+        &output.modified_ledger_entries,
+    ))
+}
+
+fn committed_effects_digest(
+    result_value: &[u8],
+    events: Vec<&[u8]>,
+    modified_ledger_entries: &[RustBuf],
+) -> RustBuf {
+    let mut hasher = Sha256::new();
+    let mut add = |buf: &[u8]| {
+        hasher.update((buf.len() as u64).to_be_bytes());
+        hasher.update(buf);
+    };
+    add(result_value);
+    let mut sorted_entries: Vec<&[u8]> = modified_ledger_entries
+        .iter()
+        .map(|buf| buf.data.as_slice())
+        .collect();
+    sorted_entries.sort();
+    for bufs in [events, sorted_entries] {
+        add(&(bufs.len() as u64).to_be_bytes());
+        for buf in bufs {
+            add(buf);
+        }
+    }
+    RustBuf::from(hasher.finalize().to_vec())
+}
+
+// Converts `cpu_insns` to the time the cost model implies for it at the
+// calibrated rate of `insns_per_nsec`, and returns its ratio to the measured
+// `time_nsecs`. Above 1 the model overcharges on this hardware, below 1 it
+// undercharges.
+#[cfg(feature = "testutils")]
+pub(crate) fn modeled_to_measured_time_ratio(
+    cpu_insns: u64,
+    time_nsecs: u64,
+    insns_per_nsec: f64,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    if insns_per_nsec.is_nan() || insns_per_nsec <= 0.0 {
+        return Err(format!(
+            "invalid calibration rate: {} instructions/ns",
+            insns_per_nsec
+        )
+        .into());
+    }
+    if time_nsecs == 0 {
+        return Err("no measured time to compare against".into());
+    }
+    let modeled_nsecs = cpu_insns as f64 / insns_per_nsec;
+    Ok(modeled_nsecs / time_nsecs as f64)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn modeled_to_measured_time_ratio(
+    _cpu_insns: u64,
+    _time_nsecs: u64,
+    _insns_per_nsec: f64,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    Err("modeled_to_measured_time_ratio is only available in testutils builds".into())
+}
+
+// The bundle is written by the host for the captured invocation's
+// `ledger_info.protocol_version`; its layout is the same for every host.
+#[cfg(feature = "testutils")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn serialize_invocation_inputs(
+    config_max_protocol: u32,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, ledger_info.protocol_version)?;
+    (hm.serialize_invocation_inputs)(
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        rent_fee_configuration,
+    )
+}
+
+#[cfg(not(feature = "testutils"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn serialize_invocation_inputs(
+    _config_max_protocol: u32,
+    _instruction_limit: u32,
+    _hf_buf: &CxxBuf,
+    _resources_buf: &CxxBuf,
+    _restored_rw_entry_indices: &[u32],
+    _source_account_buf: &CxxBuf,
+    _auth_entries: &[CxxBuf],
+    _ledger_info: &CxxLedgerInfo,
+    _ledger_entries: &[CxxBuf],
+    _ttl_entries: &[CxxBuf],
+    _base_prng_seed: &CxxBuf,
+    _rent_fee_configuration: &CxxRentFeeConfiguration,
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    Err("serialize_invocation_inputs is only available in testutils builds".into())
+}
+
+#[cfg(feature = "testutils")]
+pub(crate) fn deserialize_invocation_inputs(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    bundle: &CxxBuf,
+) -> Result<InvocationInputsBundle, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.deserialize_invocation_inputs)(bundle)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn deserialize_invocation_inputs(
+    _config_max_protocol: u32,
+    _protocol_version: u32,
+    _bundle: &CxxBuf,
+) -> Result<InvocationInputsBundle, Box<dyn std::error::Error>> {
+    Err("deserialize_invocation_inputs is only available in testutils builds".into())
+}
+
+#[cfg(feature = "testutils")]
+pub(crate) fn make_test_diagnostic_event(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    topic: &str,
+    contract_id: &[u8],
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    let contract_id: Option<[u8; 32]> = if contract_id.is_empty() {
+        None
+    } else {
+        Some(contract_id.try_into().map_err(|_| {
+            format!(
+                "contract id wrong size: got {}, expected 32",
+                contract_id.len()
+            )
+        })?)
+    };
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.make_test_diagnostic_event)(topic, contract_id)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn make_test_diagnostic_event(
+    _config_max_protocol: u32,
+    _protocol_version: u32,
+    _topic: &str,
+    _contract_id: &[u8],
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    Err("make_test_diagnostic_event is only available in testutils builds".into())
+}
+
+#[cfg(feature = "testutils")]
+pub(crate) fn summarize_auth_entry(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    buf: &CxxBuf,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.summarize_auth_entry)(buf)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn summarize_auth_entry(
+    _config_max_protocol: u32,
+    _protocol_version: u32,
+    _buf: &CxxBuf,
+) -> Result<String, Box<dyn std::error::Error>> {
+    Err("summarize_auth_entry is only available in testutils builds".into())
+}
+
+// Checks that every rate of a fee configuration is non-negative, as core
+// requires of the network settings they come from: a negative rate would
+// silently produce a wrong (possibly negative) fee.
+//
+// There are deliberately no upper bounds. `isValidConfigSettingEntry` accepts
+// any non-negative rate in a config upgrade, so any bound here would be
+// arbitrary and could reject a configuration the network voted in, making fee
+// computation fail where consensus expects it to succeed. Large rates are
+// harmless to the arithmetic itself, as the host's fee computation saturates.
+pub(crate) fn validate_fee_configuration(
+    config: &CxxFeeConfiguration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (name, rate) in [
+        (
+            "fee_per_instruction_increment",
+            config.fee_per_instruction_increment,
+        ),
+        ("fee_per_disk_read_entry", config.fee_per_disk_read_entry),
+        ("fee_per_write_entry", config.fee_per_write_entry),
+        ("fee_per_disk_read_1kb", config.fee_per_disk_read_1kb),
+        ("fee_per_write_1kb", config.fee_per_write_1kb),
+        ("fee_per_historical_1kb", config.fee_per_historical_1kb),
+        (
+            "fee_per_contract_event_1kb",
+            config.fee_per_contract_event_1kb,
+        ),
+        (
+            "fee_per_transaction_size_1kb",
+            config.fee_per_transaction_size_1kb,
+        ),
+    ] {
+        if rate < 0 {
+            return Err(
+                format!("invalid fee configuration: {} is negative ({})", name, rate).into(),
+            );
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn compute_transaction_resource_fee(
     config_max_protocol: u32,
     protocol_version: u32,
     tx_resources: CxxTransactionResources,
     fee_config: CxxFeeConfiguration,
 ) -> Result<FeePair, Box<dyn std::error::Error>> {
+    validate_fee_configuration(&fee_config)?;
     let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
     Ok((hm.compute_transaction_resource_fee)(
         tx_resources,
@@ -73,6 +707,138 @@ pub(crate) fn compute_transaction_resource_fee(
     ))
 }
 
+pub(crate) fn compute_resource_fee_delta(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    base: CxxTransactionResources,
+    adjusted: CxxTransactionResources,
+    fee_config: CxxFeeConfiguration,
+) -> Result<FeePair, Box<dyn std::error::Error>> {
+    validate_fee_configuration(&fee_config)?;
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let base_fee = (hm.compute_transaction_resource_fee)(base, fee_config.clone());
+    let adjusted_fee = (hm.compute_transaction_resource_fee)(adjusted, fee_config);
+    Ok(FeePair {
+        non_refundable_fee: adjusted_fee
+            .non_refundable_fee
+            .saturating_sub(base_fee.non_refundable_fee),
+        refundable_fee: adjusted_fee
+            .refundable_fee
+            .saturating_sub(base_fee.refundable_fee),
+    })
+}
+
+// Sums the sizes of all the buffers `invoke_host_function` takes, so that
+// callers can cap the input of an invocation before making it.
+pub(crate) fn invocation_input_size(
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+) -> usize {
+    [hf_buf, resources_buf, source_account_buf, base_prng_seed]
+        .into_iter()
+        .chain(auth_entries)
+        .chain(ledger_entries)
+        .chain(ttl_entries)
+        .map(|buf| buf.data.len())
+        .sum()
+}
+
+// Hashes everything that determines the execution of an invocation: all its
+// input buffers, the instruction limit, restored entry indices, every field of
+// the ledger info (including the cost params) and the rent fee configuration.
+// Each item is length-prefixed so that different inputs can't produce the same
+// byte stream. `enable_diagnostics` and the invocation options only shape what
+// is reported, and are left to callers to add to their keys where they vary.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn invocation_input_fingerprint(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    let mut add = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    };
+    add(&instruction_limit.to_be_bytes());
+    add(hf_buf.data.as_slice());
+    add(resources_buf.data.as_slice());
+    add(&(restored_rw_entry_indices.len() as u64).to_be_bytes());
+    for index in restored_rw_entry_indices {
+        add(&index.to_be_bytes());
+    }
+    add(source_account_buf.data.as_slice());
+    for bufs in [auth_entries, ledger_entries, ttl_entries] {
+        add(&(bufs.len() as u64).to_be_bytes());
+        for buf in bufs {
+            add(buf.data.as_slice());
+        }
+    }
+    add(base_prng_seed.data.as_slice());
+
+    add(&ledger_info.protocol_version.to_be_bytes());
+    add(&ledger_info.sequence_number.to_be_bytes());
+    add(&ledger_info.timestamp.to_be_bytes());
+    add(&ledger_info.network_id);
+    add(&ledger_info.base_reserve.to_be_bytes());
+    add(&ledger_info.memory_limit.to_be_bytes());
+    add(&ledger_info.min_temp_entry_ttl.to_be_bytes());
+    add(&ledger_info.min_persistent_entry_ttl.to_be_bytes());
+    add(&ledger_info.max_entry_ttl.to_be_bytes());
+    add(ledger_info.cpu_cost_params.data.as_slice());
+    add(ledger_info.mem_cost_params.data.as_slice());
+
+    add(&rent_fee_configuration.fee_per_write_1kb.to_be_bytes());
+    add(&rent_fee_configuration.fee_per_rent_1kb.to_be_bytes());
+    add(&rent_fee_configuration.fee_per_write_entry.to_be_bytes());
+    add(&rent_fee_configuration
+        .persistent_rent_rate_denominator
+        .to_be_bytes());
+    add(&rent_fee_configuration
+        .temporary_rent_rate_denominator
+        .to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+pub(crate) fn validate_consistent_network_ids(
+    infos: &[CxxLedgerInfo],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(first) = infos.first() else {
+        return Ok(());
+    };
+    for (i, info) in infos.iter().enumerate() {
+        if info.network_id.len() != 32 {
+            return Err(format!(
+                "ledger info {} has network ID of wrong size {}",
+                i,
+                info.network_id.len()
+            )
+            .into());
+        }
+        if info.network_id != first.network_id {
+            return Err(format!(
+                "ledger info {} has a different network ID from ledger info 0",
+                i
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn can_parse_transaction(
     config_max_protocol: u32,
     protocol_version: u32,
@@ -83,10 +849,123 @@ pub(crate) fn can_parse_transaction(
     Ok((hm.can_parse_transaction)(xdr, depth_limit))
 }
 
+// Below this many envelopes the cost of spawning threads outweighs parsing
+// them in parallel.
+const PARALLEL_PARSE_MIN_BATCH: usize = 64;
+
+// Like `can_parse_transaction`, for a batch of envelopes. The result is in
+// input order. Large batches are split across scoped threads; parsing is
+// independent per envelope so this doesn't change the result.
+pub(crate) fn can_parse_transactions(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    xdrs: &[CxxBuf],
+    depth_limit: u32,
+) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let can_parse = hm.can_parse_transaction;
+    let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if xdrs.len() < PARALLEL_PARSE_MIN_BATCH || parallelism == 1 {
+        return Ok(xdrs.iter().map(|xdr| can_parse(xdr, depth_limit)).collect());
+    }
+    let chunk_size = xdrs.len().div_ceil(parallelism);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = xdrs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|xdr| can_parse(xdr, depth_limit))
+                        .collect::<Vec<bool>>()
+                })
+            })
+            .collect();
+        let mut res = Vec::with_capacity(xdrs.len());
+        for worker in workers {
+            res.extend(
+                worker
+                    .join()
+                    .map_err(|_| "transaction parsing thread panicked")?,
+            );
+        }
+        Ok(res)
+    })
+}
+
+pub(crate) fn is_soroban_transaction(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    xdr: &CxxBuf,
+    depth_limit: u32,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.is_soroban_transaction)(xdr, depth_limit)
+}
+
+pub(crate) fn host_function_encoded_size(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    hf_buf: &CxxBuf,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.host_function_encoded_size)(hf_buf)
+}
+
+pub(crate) fn host_function_type(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    hf_buf: &CxxBuf,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.host_function_type)(hf_buf)
+}
+
+pub(crate) fn decode_ttl_entry(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    buf: &CxxBuf,
+) -> Result<DecodedTtlEntry, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let (live_until_ledger_seq, key_hash) = (hm.decode_ttl_entry)(buf)?;
+    Ok(DecodedTtlEntry {
+        live_until_ledger_seq,
+        key_hash: key_hash.to_vec(),
+    })
+}
+
+pub(crate) fn decode_source_account(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    buf: &CxxBuf,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.decode_source_account)(buf)
+}
+
+pub(crate) fn validate_footprint_disjoint(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    resources_buf: &CxxBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.validate_footprint_disjoint)(resources_buf)
+}
+
+pub(crate) fn validate_ttl_entry_correspondence(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.validate_ttl_entry_correspondence)(ledger_entries, ttl_entries)
+}
+
 pub(crate) fn compute_rent_fee(
     config_max_protocol: u32,
     protocol_version: u32,
-    changed_entries: &Vec<CxxLedgerEntryRentChange>,
+    changed_entries: &[CxxLedgerEntryRentChange],
     fee_config: CxxRentFeeConfiguration,
     current_ledger_seq: u32,
 ) -> Result<i64, Box<dyn std::error::Error>> {
@@ -98,6 +977,228 @@ pub(crate) fn compute_rent_fee(
     ))
 }
 
+pub(crate) fn compute_rent_fee_itemized(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    changed_entries: &[CxxLedgerEntryRentChange],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    Ok((hm.compute_rent_fee_itemized)(
+        changed_entries,
+        fee_config,
+        current_ledger_seq,
+    ))
+}
+
+// Computes the rent fee for temporary and persistent entries under separate
+// configurations and returns the sum. Today both configurations are normally
+// the same network setting, but keeping them apart here lets callers express
+// a split rent model without merging the results themselves.
+pub(crate) fn compute_rent_fee_split(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    temp_entries: &[CxxLedgerEntryRentChange],
+    temp_fee_config: CxxRentFeeConfiguration,
+    persistent_entries: &[CxxLedgerEntryRentChange],
+    persistent_fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let temp_fee = (hm.compute_rent_fee)(temp_entries, temp_fee_config, current_ledger_seq);
+    let persistent_fee = (hm.compute_rent_fee)(
+        persistent_entries,
+        persistent_fee_config,
+        current_ledger_seq,
+    );
+    Ok(temp_fee.saturating_add(persistent_fee))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compute_max_ttl_extension(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    entry_size_bytes: u32,
+    is_persistent: bool,
+    fee_budget: i64,
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_entry_ttl: u32,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let rent_fee_for = |new_live_until_ledger: u32| {
+        let change = CxxLedgerEntryRentChange {
+            is_persistent,
+            is_code_entry: false,
+            old_size_bytes: entry_size_bytes,
+            new_size_bytes: entry_size_bytes,
+            old_live_until_ledger: current_ledger_seq,
+            new_live_until_ledger,
+        };
+        (hm.compute_rent_fee)(&[change], fee_config.clone(), current_ledger_seq)
+    };
+    // The rent fee doesn't decrease as the live-until ledger grows, so binary
+    // search for the furthest one that fits in the budget.
+    let mut lo = current_ledger_seq;
+    let mut hi = current_ledger_seq
+        .saturating_add(max_entry_ttl)
+        .saturating_sub(1)
+        .max(current_ledger_seq);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        if rent_fee_for(mid) <= fee_budget {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    Ok(lo)
+}
+
+pub(crate) fn estimate_worst_case_fee(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    resources_buf: &CxxBuf,
+    fee_config: CxxFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_entry_ttl: u32,
+) -> Result<FeePair, Box<dyn std::error::Error>> {
+    validate_fee_configuration(&fee_config)?;
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.estimate_worst_case_fee)(
+        resources_buf,
+        fee_config,
+        rent_config,
+        current_ledger_seq,
+        max_entry_ttl,
+    )
+}
+
+// Computes all the fee components of a transaction from a single snapshot of
+// the network state: the rent write fee per 1kb is derived from
+// `bucket_list_size` and then used as the rent fee rate of `rent_fee_config`
+// (and, before protocol 23, as the write fee rate of `fee_config`), as core
+// does when it refreshes its network configuration. The rates in the passed
+// configurations are overridden accordingly.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compute_full_soroban_fee(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    tx_resources: CxxTransactionResources,
+    mut fee_config: CxxFeeConfiguration,
+    changed_entries: &[CxxLedgerEntryRentChange],
+    mut rent_fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    bucket_list_size: i64,
+    rent_write_fee_config: CxxRentWriteFeeConfiguration,
+) -> Result<SorobanFeeBreakdown, Box<dyn std::error::Error>> {
+    validate_fee_configuration(&fee_config)?;
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let write_fee_per_1kb =
+        (hm.compute_rent_write_fee_per_1kb)(bucket_list_size, rent_write_fee_config);
+    rent_fee_config.fee_per_rent_1kb = write_fee_per_1kb;
+    if protocol_version < 23 {
+        fee_config.fee_per_write_1kb = write_fee_per_1kb;
+    }
+    let resource_fee = (hm.compute_transaction_resource_fee)(tx_resources, fee_config);
+    let rent_fee = (hm.compute_rent_fee)(changed_entries, rent_fee_config, current_ledger_seq);
+    Ok(SorobanFeeBreakdown {
+        non_refundable_fee: resource_fee.non_refundable_fee,
+        refundable_fee: resource_fee.refundable_fee,
+        rent_fee,
+        write_fee_per_1kb,
+    })
+}
+
+// Checks the refundable fees an invocation consumed against the refundable
+// fee the transaction declared, in the same order and with the same messages
+// as `RefundableFeeTracker::consumeRefundableSorobanResources`: rent is
+// covered first, and the refundable resource fee from what remains.
+pub(crate) fn check_refundable_allowance(
+    computed_refundable: i64,
+    computed_rent: i64,
+    declared_allowance: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if computed_refundable < 0 || computed_rent < 0 || declared_allowance < 0 {
+        return Err(format!(
+            "negative refundable fee: refundable {}, rent {}, allowance {}",
+            computed_refundable, computed_rent, declared_allowance
+        )
+        .into());
+    }
+    if declared_allowance < computed_rent {
+        return Err(format!(
+            "refundable resource fee was not sufficient to cover the ledger storage rent: {} > {}",
+            computed_rent, declared_allowance
+        )
+        .into());
+    }
+    if declared_allowance - computed_rent < computed_refundable {
+        return Err(format!(
+            "refundable resource fee was not sufficient to cover the events fee after paying for \
+             ledger storage rent: {} > {}",
+            computed_refundable,
+            declared_allowance - computed_rent
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn estimate_max_ttl_bump_fee(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    resources_buf: &CxxBuf,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_ttl: u32,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.estimate_max_ttl_bump_fee)(
+        resources_buf,
+        ledger_entries,
+        ttl_entries,
+        fee_config,
+        current_ledger_seq,
+        max_ttl,
+        cpu_cost_params,
+        mem_cost_params,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compute_restore_footprint_fee(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    entries: &[CxxBuf],
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+    bucket_list_size: i64,
+    write_config: CxxRentWriteFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    min_persistent_ttl: u32,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.compute_restore_footprint_fee)(
+        entries,
+        cpu_cost_params,
+        mem_cost_params,
+        bucket_list_size,
+        write_config,
+        rent_config,
+        current_ledger_seq,
+        min_persistent_ttl,
+    )
+}
+
 pub(crate) fn compute_rent_write_fee_per_1kb(
     config_max_protocol: u32,
     protocol_version: u32,
@@ -110,3 +1211,109 @@ pub(crate) fn compute_rent_write_fee_per_1kb(
         fee_config,
     ))
 }
+
+pub(crate) fn estimate_rent_fee_curve(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    sizes: &[i64],
+    fee_config: CxxRentWriteFeeConfiguration,
+) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    Ok(sizes
+        .iter()
+        .map(|size| (hm.compute_rent_write_fee_per_1kb)(*size, fee_config.clone()))
+        .collect())
+}
+
+pub(crate) fn auth_complexity(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    auth_entries: &[CxxBuf],
+    depth_limit: u32,
+) -> Result<AuthComplexity, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let (entry_count, max_nesting_depth) = (hm.auth_complexity)(auth_entries, depth_limit)?;
+    Ok(AuthComplexity {
+        entry_count: entry_count as u64,
+        max_nesting_depth,
+    })
+}
+
+pub(crate) fn validate_contract_code_entry(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    buf: &CxxBuf,
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let hash = (hm.validate_contract_code_entry)(buf)?;
+    Ok(RustBuf::from(hash.to_vec()))
+}
+
+pub(crate) fn extract_referenced_code_hashes(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    resources_buf: &CxxBuf,
+    ledger_entries: &[CxxBuf],
+) -> Result<Vec<RustBuf>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let hashes = (hm.extract_referenced_code_hashes)(resources_buf, ledger_entries)?;
+    Ok(hashes
+        .iter()
+        .map(|hash| RustBuf::from(hash.to_vec()))
+        .collect())
+}
+
+pub(crate) fn group_diagnostic_events_by_contract(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    events: &[RustBuf],
+) -> Result<Vec<DiagnosticEventGroup>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let groups = (hm.group_diagnostic_events_by_contract)(events)?;
+    Ok(groups
+        .into_iter()
+        .map(|(contract_id, events)| DiagnosticEventGroup {
+            contract_id: contract_id.to_vec(),
+            events,
+        })
+        .collect())
+}
+
+pub(crate) fn invoked_contract_address(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    hf_buf: &CxxBuf,
+) -> Result<RustBuf, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let id = (hm.invoked_contract_address)(hf_buf)?;
+    Ok(RustBuf::from(id.map(|id| id.to_vec()).unwrap_or_default()))
+}
+
+#[test]
+fn digest_keeps_event_order_but_not_entry_order() {
+    let bufs = |bytes: &[u8]| -> Vec<RustBuf> { bytes.iter().map(|b| vec![*b].into()).collect() };
+    let digest = |events: &[u8], entries: &[u8]| {
+        let events = events.iter().map(std::slice::from_ref).collect();
+        committed_effects_digest(&[9], events, &bufs(entries)).data
+    };
+    assert_eq!(digest(&[1, 2], &[3, 4]), digest(&[1, 2], &[4, 3]));
+    assert_ne!(digest(&[1, 2], &[3, 4]), digest(&[2, 1], &[3, 4]));
+}
+
+#[test]
+fn fee_configuration_with_a_negative_rate_is_rejected() {
+    let config = |fee_per_write_1kb| CxxFeeConfiguration {
+        fee_per_instruction_increment: 25,
+        fee_per_disk_read_entry: 6250,
+        fee_per_write_entry: 10000,
+        fee_per_disk_read_1kb: 1786,
+        fee_per_write_1kb,
+        fee_per_historical_1kb: 16235,
+        fee_per_contract_event_1kb: 10000,
+        fee_per_transaction_size_1kb: 1624,
+    };
+    assert!(validate_fee_configuration(&config(0)).is_ok());
+    assert!(validate_fee_configuration(&config(i64::MAX)).is_ok());
+    let err = validate_fee_configuration(&config(-1)).unwrap_err();
+    assert!(err.to_string().contains("fee_per_write_1kb"));
+}
@@ -15,7 +15,7 @@
 // cache (if it exists) so that we can upgrade without stalling.
 
 use crate::{
-    rust_bridge::CxxBuf,
+    rust_bridge::{CpuMemCost, CxxBuf, RustBuf},
     soroban_proto_all::{get_host_module_for_protocol, p23, protocol_agnostic},
 };
 
@@ -35,27 +35,117 @@ impl SorobanModuleCache {
         _wasm: &[u8],
     ) -> Result<(), Box<dyn std::error::Error>> {
         match ledger_protocol {
-            23 => self.p23_cache.compile(_wasm),
+            23 => self.p23_cache.compile(ledger_protocol, _wasm),
             // Currently p24 uses the same env as p23 and thus can keep using
             // p23 module cache.
             #[cfg(feature = "next")]
-            24 => self.p23_cache.compile(_wasm),
+            24 => self.p23_cache.compile(ledger_protocol, _wasm),
             // Add other protocols here as needed.
             _ => Err(protocol_agnostic::make_error("unsupported protocol")),
         }
     }
+    pub fn compile_timed(
+        &mut self,
+        ledger_protocol: u32,
+        _wasm: &[u8],
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        match ledger_protocol {
+            23 => self.p23_cache.compile_timed(ledger_protocol, _wasm),
+            #[cfg(feature = "next")]
+            24 => self.p23_cache.compile_timed(ledger_protocol, _wasm),
+            _ => Err(protocol_agnostic::make_error("unsupported protocol")),
+        }
+    }
+    pub fn validate_wasm(
+        &self,
+        ledger_protocol: u32,
+        _wasm: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match ledger_protocol {
+            23 => self.p23_cache.validate_wasm(_wasm),
+            #[cfg(feature = "next")]
+            24 => self.p23_cache.validate_wasm(_wasm),
+            _ => Err(protocol_agnostic::make_error("unsupported protocol")),
+        }
+    }
+    pub fn inspect_wasm_exports(
+        &self,
+        ledger_protocol: u32,
+        _wasm: &[u8],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        match ledger_protocol {
+            23 => self.p23_cache.inspect_wasm_exports(_wasm),
+            #[cfg(feature = "next")]
+            24 => self.p23_cache.inspect_wasm_exports(_wasm),
+            _ => Err(protocol_agnostic::make_error("unsupported protocol")),
+        }
+    }
+    pub fn wasm_within_size_limit(
+        &self,
+        ledger_protocol: u32,
+        _wasm: &[u8],
+        max_contract_size_bytes: u32,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        match ledger_protocol {
+            23 => self
+                .p23_cache
+                .wasm_within_size_limit(_wasm, max_contract_size_bytes),
+            #[cfg(feature = "next")]
+            24 => self
+                .p23_cache
+                .wasm_within_size_limit(_wasm, max_contract_size_bytes),
+            _ => Err(protocol_agnostic::make_error("unsupported protocol")),
+        }
+    }
+    pub fn estimate_compile_memory(
+        &self,
+        ledger_protocol: u32,
+        _wasm: &[u8],
+        cpu_cost_params: &CxxBuf,
+        mem_cost_params: &CxxBuf,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        match ledger_protocol {
+            23 => self
+                .p23_cache
+                .estimate_compile_memory(_wasm, cpu_cost_params, mem_cost_params),
+            #[cfg(feature = "next")]
+            24 => self
+                .p23_cache
+                .estimate_compile_memory(_wasm, cpu_cost_params, mem_cost_params),
+            _ => Err(protocol_agnostic::make_error("unsupported protocol")),
+        }
+    }
     pub fn shallow_clone(&self) -> Result<Box<Self>, Box<dyn std::error::Error>> {
         Ok(Box::new(Self {
             p23_cache: self.p23_cache.shallow_clone()?,
         }))
     }
 
-    pub fn evict_contract_code(&mut self, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn evict_contract_code(
+        &mut self,
+        key: &[u8],
+        error_if_pinned: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let _hash: [u8; 32] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| "Invalid contract-code key length")?;
+        self.p23_cache.evict(&_hash, error_if_pinned)
+    }
+    pub fn pin_module(&mut self, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let _hash: [u8; 32] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| "Invalid contract-code key length")?;
+        self.p23_cache.pin_module(&_hash)?;
+        Ok(())
+    }
+    pub fn unpin_module(&mut self, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         let _hash: [u8; 32] = key
             .as_ref()
             .try_into()
             .map_err(|_| "Invalid contract-code key length")?;
-        self.p23_cache.evict(&_hash)?;
+        self.p23_cache.unpin_module(&_hash)?;
         Ok(())
     }
     pub fn clear(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -79,6 +169,55 @@ impl SorobanModuleCache {
             _ => Err(protocol_agnostic::make_error("unsupported protocol")),
         }
     }
+    // Looks `key` up `iterations` times and returns the average nanoseconds
+    // per lookup, to tell whether the cache's internal locking is a
+    // bottleneck under concurrent use.
+    #[cfg(feature = "testutils")]
+    pub fn benchmark_cache_lookup(
+        &self,
+        key: &[u8],
+        iterations: u32,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let _hash: [u8; 32] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| "Invalid contract-code key length")?;
+        if iterations == 0 {
+            return Err(protocol_agnostic::make_error("no lookup iterations"));
+        }
+        let start = std::time::Instant::now();
+        for _ in 0..iterations {
+            self.p23_cache.contains_module(&_hash)?;
+        }
+        Ok((start.elapsed().as_nanos() / iterations as u128) as u64)
+    }
+    #[cfg(not(feature = "testutils"))]
+    pub fn benchmark_cache_lookup(
+        &self,
+        _key: &[u8],
+        _iterations: u32,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Err(protocol_agnostic::make_error(
+            "benchmark_cache_lookup is only available in testutils builds",
+        ))
+    }
+    pub fn export_cached_keys(&self) -> Result<Vec<RustBuf>, Box<dyn std::error::Error>> {
+        Ok(self
+            .p23_cache
+            .export_cached_keys()?
+            .iter()
+            .map(|key| RustBuf::from(key.to_vec()))
+            .collect())
+    }
+    // Returns the protocol version the module for `key` was compiled for, or
+    // 0 if it isn't cached.
+    pub fn module_protocol_version(&self, key: &[u8]) -> Result<u32, Box<dyn std::error::Error>> {
+        let hash: [u8; 32] = key
+            .as_ref()
+            .try_into()
+            .map_err(|_| "Invalid contract-code key length")?;
+        Ok(self.p23_cache.module_protocol_version(&hash)?.unwrap_or(0))
+    }
     pub fn get_mem_bytes_consumed(&self) -> Result<u64, Box<dyn std::error::Error>> {
         #[allow(unused_mut)]
         let mut bytes = 0;
@@ -101,3 +240,121 @@ pub(crate) fn contract_code_memory_size_for_rent(
     let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
     (hm.contract_code_memory_size_for_rent)(contract_code_entry, cpu_cost_params, mem_cost_params)
 }
+
+pub(crate) fn get_supported_wasm_features(
+    config_max_protocol: u32,
+    protocol_version: u32,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    Ok((hm.get_supported_wasm_features)())
+}
+
+pub(crate) fn describe_cost_model(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.describe_cost_model)(cpu_cost_params, mem_cost_params)
+}
+
+#[cfg(feature = "testutils")]
+pub(crate) fn diff_contract_cost_params(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    old: &CxxBuf,
+    new: &CxxBuf,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.diff_contract_cost_params)(old, new)
+}
+
+#[cfg(not(feature = "testutils"))]
+pub(crate) fn diff_contract_cost_params(
+    _config_max_protocol: u32,
+    _protocol_version: u32,
+    _old: &CxxBuf,
+    _new: &CxxBuf,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Err("diff_contract_cost_params is only available in testutils builds".into())
+}
+
+pub(crate) fn ledger_entry_rent_bytes(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    entry: &CxxBuf,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    (hm.ledger_entry_rent_bytes)(entry, cpu_cost_params, mem_cost_params)
+}
+
+pub(crate) fn vm_instantiation_cost(
+    config_max_protocol: u32,
+    protocol_version: u32,
+    contract_code_entry: &CxxBuf,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<CpuMemCost, Box<dyn std::error::Error>> {
+    let hm = get_host_module_for_protocol(config_max_protocol, protocol_version)?;
+    let (cpu_insns, mem_bytes) =
+        (hm.vm_instantiation_cost)(contract_code_entry, cpu_cost_params, mem_cost_params)?;
+    Ok(CpuMemCost {
+        cpu_insns,
+        mem_bytes,
+    })
+}
+
+#[test]
+fn inspect_wasm_exports_lists_contract_functions() {
+    let cache = SorobanModuleCache::new().unwrap();
+    let exports = cache
+        .inspect_wasm_exports(23, soroban_test_wasms::ADD_I32)
+        .unwrap();
+    assert!(exports.iter().any(|name| name == "add"));
+    assert!(cache.export_cached_keys().unwrap().is_empty());
+}
+
+#[test]
+fn clear_and_evict_leave_pinned_modules_cached() {
+    use sha2::{Digest, Sha256};
+    let mut cache = SorobanModuleCache::new().unwrap();
+    let add_key: [u8; 32] = Sha256::digest(soroban_test_wasms::ADD_I32).into();
+    let sum_key: [u8; 32] = Sha256::digest(soroban_test_wasms::SUM_I32).into();
+    cache.compile(23, soroban_test_wasms::ADD_I32).unwrap();
+    cache.compile(23, soroban_test_wasms::SUM_I32).unwrap();
+    cache.pin_module(&add_key).unwrap();
+
+    cache.clear().unwrap();
+    assert!(cache.contains_module(23, &add_key).unwrap());
+    assert!(!cache.contains_module(23, &sum_key).unwrap());
+    let keys = cache.export_cached_keys().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].data, add_key.to_vec());
+
+    assert!(!cache.evict_contract_code(&add_key, false).unwrap());
+    assert!(cache.evict_contract_code(&add_key, true).is_err());
+    assert!(cache.contains_module(23, &add_key).unwrap());
+    cache.unpin_module(&add_key).unwrap();
+    assert!(cache.evict_contract_code(&add_key, false).unwrap());
+    assert!(!cache.contains_module(23, &add_key).unwrap());
+    assert!(!cache.evict_contract_code(&add_key, false).unwrap());
+}
+
+#[test]
+fn module_protocol_version_is_the_compiling_ledger_protocol() {
+    use sha2::{Digest, Sha256};
+    let mut cache = SorobanModuleCache::new().unwrap();
+    let add_key: [u8; 32] = Sha256::digest(soroban_test_wasms::ADD_I32).into();
+    assert_eq!(cache.module_protocol_version(&add_key).unwrap(), 0);
+    cache.compile(23, soroban_test_wasms::ADD_I32).unwrap();
+    assert_eq!(cache.module_protocol_version(&add_key).unwrap(), 23);
+    #[cfg(feature = "next")]
+    {
+        let sum_key: [u8; 32] = Sha256::digest(soroban_test_wasms::SUM_I32).into();
+        cache.compile(24, soroban_test_wasms::SUM_I32).unwrap();
+        assert_eq!(cache.module_protocol_version(&sum_key).unwrap(), 24);
+    }
+}
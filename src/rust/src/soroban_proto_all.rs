@@ -1,10 +1,9 @@
 use crate::{
-    CxxBuf, CxxFeeConfiguration, CxxLedgerEntryRentChange, CxxLedgerInfo, CxxRentFeeConfiguration,
-    CxxRentWriteFeeConfiguration, CxxTransactionResources, FeePair, InvokeHostFunctionOutput,
-    SorobanModuleCache, SorobanVersionInfo,
+    CxxBuf, CxxFeeConfiguration, CxxInvokeHostFunctionOptions, CxxLedgerEntryRentChange,
+    CxxLedgerInfo, CxxRentFeeConfiguration, CxxRentWriteFeeConfiguration, CxxTransactionResources,
+    FeePair, InvokeHostFunctionOutput, SorobanModuleCache, SorobanVersionInfo, VersionTiming,
 };
 
-#[cfg(feature = "testutils")]
 use crate::RustBuf;
 
 // We have multiple copies of soroban linked into stellar-core here. This is
@@ -46,6 +45,28 @@ pub(crate) mod protocol_agnostic {
     // ever plausibly change. If they ever _do_ change we can switch this (and
     // the callers) to pass a protocol number but it seems unlikely.
     pub(crate) use super::p23::soroban_env_host::xdr::int128_helpers;
+
+    // Splits a buffer packed by the `pack_events` invocation option back into
+    // the buffers it holds, each of which is prefixed with its length as an
+    // 8-byte big-endian integer.
+    pub(crate) fn unpack_buffers(
+        mut packed: &[u8],
+    ) -> Result<Vec<&[u8]>, Box<dyn std::error::Error>> {
+        let mut bufs = vec![];
+        while !packed.is_empty() {
+            let Some((len, rest)) = packed.split_first_chunk::<8>() else {
+                return Err(make_error("packed buffer has a truncated length"));
+            };
+            let len = u64::from_be_bytes(*len);
+            if len > rest.len() as u64 {
+                return Err(make_error("packed buffer has a truncated buffer"));
+            }
+            let (buf, rest) = rest.split_at(len as usize);
+            bufs.push(buf);
+            packed = rest;
+        }
+        Ok(bufs)
+    }
 }
 
 #[path = "."]
@@ -84,10 +105,77 @@ pub(crate) mod p23 {
         v.interface.pre_release
     }
 
+    // The lowest ledger protocol this host executes; it agrees with the host's
+    // own (crate-private) `MIN_LEDGER_PROTOCOL_VERSION`.
+    pub(crate) const MIN_LEDGER_PROTOCOL_VERSION: u32 = 23;
+
+    // The optional wasm proposals this host's (crate-private) wasmi config
+    // enables; every other proposal, including floats, is turned off.
+    pub(crate) const SUPPORTED_WASM_FEATURES: &[&str] =
+        &["bulk-memory", "mutable-global", "sign-extension"];
+
     pub(crate) const fn get_version_protocol(v: &soroban_env_host::Version) -> u32 {
         v.interface.protocol
     }
 
+    // The size of the entry before the change, 0 if it did not exist, was
+    // expired or is being restored.
+    pub(crate) fn ledger_entry_change_old_size(change: &e2e_invoke::LedgerEntryChange) -> u32 {
+        change.old_entry_size_bytes_for_rent
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contract_data_ttl_change(
+        key_hash: Vec<u8>,
+        old_live_until_ledger: u32,
+        new_live_until_ledger: u32,
+    ) -> e2e_invoke::LedgerEntryLiveUntilChange {
+        e2e_invoke::LedgerEntryLiveUntilChange {
+            key_hash,
+            entry_type: soroban_env_host::xdr::LedgerEntryType::ContractData,
+            durability: soroban_env_host::xdr::ContractDataDurability::Persistent,
+            old_live_until_ledger,
+            new_live_until_ledger,
+        }
+    }
+
+    // Names of the functions exported by the module cached for `key`, if it
+    // is cached.
+    pub(crate) fn module_function_exports(
+        module_cache: &ModuleCache,
+        key: &soroban_env_host::xdr::Hash,
+    ) -> Result<Option<Vec<String>>, HostError> {
+        Ok(module_cache.get_module(key)?.map(|module| {
+            module
+                .wasmi_module
+                .exports()
+                .filter(|export| export.ty().func().is_some())
+                .map(|export| export.name().to_string())
+                .collect()
+        }))
+    }
+
+    // The bytes the footprint reads from disk (`disk_read_bytes` from p23 on).
+    pub(crate) fn soroban_resources_read_bytes(
+        resources: &soroban_env_host::xdr::SorobanResources,
+    ) -> u32 {
+        resources.disk_read_bytes
+    }
+
+    pub(crate) fn make_soroban_resources(
+        footprint: soroban_env_host::xdr::LedgerFootprint,
+        instructions: u32,
+        read_bytes: u32,
+        write_bytes: u32,
+    ) -> soroban_env_host::xdr::SorobanResources {
+        soroban_env_host::xdr::SorobanResources {
+            footprint,
+            instructions,
+            disk_read_bytes: read_bytes,
+            write_bytes,
+        }
+    }
+
     pub fn invoke_host_function_with_trace_hook_and_module_cache<
         T: AsRef<[u8]>,
         I: ExactSizeIterator<Item = T>,
@@ -278,10 +366,68 @@ pub(crate) mod p22 {
         v.interface.pre_release
     }
 
+    // The lowest ledger protocol this host executes; it agrees with the host's
+    // own (crate-private) `MIN_LEDGER_PROTOCOL_VERSION`.
+    pub(crate) const MIN_LEDGER_PROTOCOL_VERSION: u32 = 22;
+
+    // The optional wasm proposals this host's (crate-private) wasmi config
+    // enables; every other proposal, including floats, is turned off.
+    pub(crate) const SUPPORTED_WASM_FEATURES: &[&str] =
+        &["bulk-memory", "mutable-global", "sign-extension"];
+
     pub(crate) const fn get_version_protocol(v: &soroban_env_host::Version) -> u32 {
         v.interface.protocol
     }
 
+    // The size of the entry before the change, 0 if it did not exist, was
+    // expired or is being restored.
+    pub(crate) fn ledger_entry_change_old_size(change: &e2e_invoke::LedgerEntryChange) -> u32 {
+        change.old_entry_size_bytes
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contract_data_ttl_change(
+        key_hash: Vec<u8>,
+        old_live_until_ledger: u32,
+        new_live_until_ledger: u32,
+    ) -> e2e_invoke::LedgerEntryLiveUntilChange {
+        e2e_invoke::LedgerEntryLiveUntilChange {
+            key_hash,
+            durability: soroban_env_host::xdr::ContractDataDurability::Persistent,
+            old_live_until_ledger,
+            new_live_until_ledger,
+        }
+    }
+
+    // Hosts before p23 don't have a module cache.
+    pub(crate) fn module_function_exports(
+        _module_cache: &ModuleCache,
+        _key: &Hash,
+    ) -> Result<Option<Vec<String>>, HostError> {
+        Err(INTERNAL_ERROR.into())
+    }
+
+    // The bytes the footprint reads from disk (`disk_read_bytes` from p23 on).
+    pub(crate) fn soroban_resources_read_bytes(
+        resources: &soroban_env_host::xdr::SorobanResources,
+    ) -> u32 {
+        resources.read_bytes
+    }
+
+    pub(crate) fn make_soroban_resources(
+        footprint: soroban_env_host::xdr::LedgerFootprint,
+        instructions: u32,
+        read_bytes: u32,
+        write_bytes: u32,
+    ) -> soroban_env_host::xdr::SorobanResources {
+        soroban_env_host::xdr::SorobanResources {
+            footprint,
+            instructions,
+            read_bytes,
+            write_bytes,
+        }
+    }
+
     pub fn invoke_host_function_with_trace_hook_and_module_cache<
         T: AsRef<[u8]>,
         I: ExactSizeIterator<Item = T>,
@@ -467,10 +613,68 @@ pub(crate) mod p21 {
         soroban_env_host::meta::get_pre_release_version(v.interface)
     }
 
+    // The lowest ledger protocol this host executes. Soroban was introduced in
+    // protocol 20, which the p21 host still supports.
+    pub(crate) const MIN_LEDGER_PROTOCOL_VERSION: u32 = 20;
+
+    // The optional wasm proposals this host's (crate-private) wasmi config
+    // enables; every other proposal, including floats, is turned off.
+    pub(crate) const SUPPORTED_WASM_FEATURES: &[&str] =
+        &["bulk-memory", "mutable-global", "sign-extension"];
+
     pub(crate) const fn get_version_protocol(v: &soroban_env_host::Version) -> u32 {
         soroban_env_host::meta::get_ledger_protocol_version(v.interface)
     }
 
+    // The size of the entry before the change, 0 if it did not exist, was
+    // expired or is being restored.
+    pub(crate) fn ledger_entry_change_old_size(change: &e2e_invoke::LedgerEntryChange) -> u32 {
+        change.old_entry_size_bytes
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contract_data_ttl_change(
+        key_hash: Vec<u8>,
+        old_live_until_ledger: u32,
+        new_live_until_ledger: u32,
+    ) -> e2e_invoke::LedgerEntryLiveUntilChange {
+        e2e_invoke::LedgerEntryLiveUntilChange {
+            key_hash,
+            durability: soroban_env_host::xdr::ContractDataDurability::Persistent,
+            old_live_until_ledger,
+            new_live_until_ledger,
+        }
+    }
+
+    // Hosts before p23 don't have a module cache.
+    pub(crate) fn module_function_exports(
+        _module_cache: &ModuleCache,
+        _key: &Hash,
+    ) -> Result<Option<Vec<String>>, HostError> {
+        Err(INTERNAL_ERROR.into())
+    }
+
+    // The bytes the footprint reads from disk (`disk_read_bytes` from p23 on).
+    pub(crate) fn soroban_resources_read_bytes(
+        resources: &soroban_env_host::xdr::SorobanResources,
+    ) -> u32 {
+        resources.read_bytes
+    }
+
+    pub(crate) fn make_soroban_resources(
+        footprint: soroban_env_host::xdr::LedgerFootprint,
+        instructions: u32,
+        read_bytes: u32,
+        write_bytes: u32,
+    ) -> soroban_env_host::xdr::SorobanResources {
+        soroban_env_host::xdr::SorobanResources {
+            footprint,
+            instructions,
+            read_bytes,
+            write_bytes,
+        }
+    }
+
     pub fn invoke_host_function_with_trace_hook_and_module_cache<
         T: AsRef<[u8]>,
         I: ExactSizeIterator<Item = T>,
@@ -630,6 +834,154 @@ pub(crate) fn get_soroban_version_info(core_max_proto: u32) -> Vec<SorobanVersio
     infos
 }
 
+// Returns the version info of every soroban host linked into this binary, one
+// per adaptor module, in ascending protocol order. Unlike
+// `get_soroban_version_info` this doesn't check the hosts against core's
+// protocol, so it can't panic.
+pub(crate) fn list_linked_soroban_versions() -> Vec<SorobanVersionInfo> {
+    let curr_max_proto = soroban_curr::soroban_proto_any::get_max_proto();
+    HOST_MODULES
+        .iter()
+        .map(|f| (f.get_soroban_version_info)(curr_max_proto))
+        .collect()
+}
+
+// Runs a trivial invocation on every linked soroban host, so that one-time
+// initialization costs are paid at startup rather than by the first real
+// transaction. No ledger state is involved.
+pub(crate) fn warmup() -> Result<(), Box<dyn std::error::Error>> {
+    for hm in HOST_MODULES {
+        (hm.warmup)()?;
+    }
+    Ok(())
+}
+
+pub(crate) fn get_per_version_timings() -> Vec<VersionTiming> {
+    if !cfg!(feature = "version-timings") {
+        return vec![];
+    }
+    HOST_MODULES
+        .iter()
+        .map(|hm| VersionTiming {
+            protocol_version: hm.max_proto,
+            cumulative_nanos: (hm.get_invoke_nanos)(),
+        })
+        .collect()
+}
+
+// Signatures of the `HostModule` entries below that take long argument lists
+// or share a shape, named here to keep the struct readable.
+type HostResult<T> = Result<T, Box<dyn std::error::Error>>;
+type InvokeHostFunctionFn = fn(
+    enable_diagnostics: bool,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+) -> HostResult<InvokeHostFunctionOutput>;
+type InvokeWithDiagnosticSinkFn = fn(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &SorobanModuleCache,
+    diagnostic_sink: &mut dyn FnMut(RustBuf),
+) -> HostResult<InvokeHostFunctionOutput>;
+type InvokeAndCollectEventsFn = fn(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    module_cache: &SorobanModuleCache,
+) -> HostResult<Vec<RustBuf>>;
+#[cfg(feature = "testutils")]
+type SerializeInvocationInputsFn = fn(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+) -> HostResult<RustBuf>;
+#[cfg(feature = "testutils")]
+type MeasureMarshallingOverheadFn = fn(
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+) -> HostResult<u64>;
+type EstimateWorstCaseFeeFn = fn(
+    resources_buf: &CxxBuf,
+    fee_config: CxxFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_entry_ttl: u32,
+) -> HostResult<FeePair>;
+type EstimateMaxTtlBumpFeeFn = fn(
+    resources_buf: &CxxBuf,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_ttl: u32,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> HostResult<i64>;
+type ComputeRestoreFootprintFeeFn = fn(
+    entries: &[CxxBuf],
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+    bucket_list_size: i64,
+    write_config: CxxRentWriteFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    min_persistent_ttl: u32,
+) -> HostResult<i64>;
+type AuthComplexityFn = fn(auth_entries: &[CxxBuf], depth_limit: u32) -> HostResult<(usize, u32)>;
+type ExtractReferencedCodeHashesFn =
+    fn(resources_buf: &CxxBuf, ledger_entries: &[CxxBuf]) -> HostResult<Vec<[u8; 32]>>;
+type GroupDiagnosticEventsFn = fn(events: &[RustBuf]) -> HostResult<Vec<([u8; 32], Vec<RustBuf>)>>;
+type RentFeeFn<T> = fn(
+    changed_entries: &[CxxLedgerEntryRentChange],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+) -> T;
+// Functions of one XDR buffer plus the network's CPU and memory cost params.
+type CostParamsFn<T> =
+    fn(buf: &CxxBuf, cpu_cost_params: &CxxBuf, mem_cost_params: &CxxBuf) -> HostResult<T>;
+// Functions decoding or validating a single XDR buffer.
+type BufFn<T> = fn(buf: &CxxBuf) -> HostResult<T>;
+
 // Rust does not support first-class modules. This means we cannot put multiple
 // modules into an array and iterate over it switching between them by protocol
 // number. Which is what we want to do! But as a workaround, we can copy
@@ -642,65 +994,124 @@ pub(crate) struct HostModule {
     // dispatch. The struct returned from `get_version_info` contains a bunch of
     // dynamic strings, which is necessary due to cxx limitations.
     pub(crate) max_proto: u32,
+    pub(crate) min_proto: u32,
     pub(crate) get_soroban_version_info: fn(u32) -> SorobanVersionInfo,
-    pub(crate) invoke_host_function:
-        fn(
-            enable_diagnostics: bool,
-            instruction_limit: u32,
-            hf_buf: &CxxBuf,
-            resources_buf: &CxxBuf,
-            restored_rw_entry_indices: &Vec<u32>,
-            source_account_buf: &CxxBuf,
-            auth_entries: &Vec<CxxBuf>,
-            ledger_info: &CxxLedgerInfo,
-            ledger_entries: &Vec<CxxBuf>,
-            ttl_entries: &Vec<CxxBuf>,
-            base_prng_seed: &CxxBuf,
-            rent_fee_configuration: &CxxRentFeeConfiguration,
-            module_cache: &SorobanModuleCache,
-        ) -> Result<InvokeHostFunctionOutput, Box<dyn std::error::Error>>,
+    pub(crate) warmup: fn() -> HostResult<()>,
+    pub(crate) get_invoke_nanos: fn() -> u64,
+    pub(crate) invoke_host_function: InvokeHostFunctionFn,
+    pub(crate) invoke_host_function_with_diagnostic_sink: InvokeWithDiagnosticSinkFn,
+    pub(crate) invoke_and_collect_events: InvokeAndCollectEventsFn,
     pub(crate) compute_transaction_resource_fee:
         fn(tx_resources: CxxTransactionResources, fee_config: CxxFeeConfiguration) -> FeePair,
-    pub(crate) compute_rent_fee: fn(
-        changed_entries: &Vec<CxxLedgerEntryRentChange>,
-        fee_config: CxxRentFeeConfiguration,
-        current_ledger_seq: u32,
-    ) -> i64,
+    pub(crate) compute_rent_fee: RentFeeFn<i64>,
+    pub(crate) compute_rent_fee_itemized: RentFeeFn<Vec<i64>>,
+    pub(crate) estimate_worst_case_fee: EstimateWorstCaseFeeFn,
+    pub(crate) estimate_max_ttl_bump_fee: EstimateMaxTtlBumpFeeFn,
     pub(crate) compute_rent_write_fee_per_1kb:
         fn(bucket_list_size: i64, fee_config: CxxRentWriteFeeConfiguration) -> i64,
-    pub(crate) contract_code_memory_size_for_rent: fn(
-        contract_code_entry: &CxxBuf,
-        cpu_cost_params: &CxxBuf,
-        mem_cost_params: &CxxBuf,
-    )
-        -> Result<u32, Box<dyn std::error::Error>>,
+    pub(crate) contract_code_memory_size_for_rent: CostParamsFn<u32>,
+    pub(crate) compute_restore_footprint_fee: ComputeRestoreFootprintFeeFn,
+    pub(crate) get_supported_wasm_features: fn() -> Vec<String>,
+    pub(crate) describe_cost_model:
+        fn(cpu_cost_params: &CxxBuf, mem_cost_params: &CxxBuf) -> HostResult<Vec<String>>,
+    pub(crate) ledger_entry_rent_bytes: CostParamsFn<u32>,
+    pub(crate) vm_instantiation_cost: CostParamsFn<(u64, u64)>,
     pub(crate) can_parse_transaction: fn(&CxxBuf, depth_limit: u32) -> bool,
+    pub(crate) is_soroban_transaction: fn(&CxxBuf, depth_limit: u32) -> HostResult<bool>,
+    pub(crate) host_function_encoded_size: BufFn<usize>,
+    pub(crate) host_function_type: BufFn<u32>,
+    pub(crate) decode_ttl_entry: BufFn<(u32, [u8; 32])>,
+    pub(crate) decode_source_account: BufFn<String>,
+    pub(crate) validate_footprint_disjoint: BufFn<()>,
+    pub(crate) validate_ttl_entry_correspondence:
+        fn(ledger_entries: &[CxxBuf], ttl_entries: &[CxxBuf]) -> HostResult<()>,
+    pub(crate) auth_complexity: AuthComplexityFn,
+    pub(crate) validate_contract_code_entry: BufFn<[u8; 32]>,
+    pub(crate) extract_referenced_code_hashes: ExtractReferencedCodeHashesFn,
+    pub(crate) invoked_contract_address: BufFn<Option<[u8; 32]>>,
+    pub(crate) group_diagnostic_events_by_contract: GroupDiagnosticEventsFn,
     #[cfg(feature = "testutils")]
     pub(crate) rustbuf_containing_scval_to_string: fn(&RustBuf) -> String,
     #[cfg(feature = "testutils")]
     pub(crate) rustbuf_containing_diagnostic_event_to_string: fn(&RustBuf) -> String,
+    #[cfg(feature = "testutils")]
+    pub(crate) measure_marshalling_overhead: MeasureMarshallingOverheadFn,
+    #[cfg(feature = "testutils")]
+    pub(crate) serialize_invocation_inputs: SerializeInvocationInputsFn,
+    #[cfg(feature = "testutils")]
+    pub(crate) deserialize_invocation_inputs: BufFn<crate::InvocationInputsBundle>,
+    #[cfg(feature = "testutils")]
+    pub(crate) make_test_diagnostic_event:
+        fn(topic: &str, contract_id: Option<[u8; 32]>) -> HostResult<RustBuf>,
+    #[cfg(feature = "testutils")]
+    pub(crate) summarize_auth_entry: BufFn<String>,
+    #[cfg(feature = "testutils")]
+    pub(crate) diff_contract_cost_params: fn(old: &CxxBuf, new: &CxxBuf) -> HostResult<Vec<String>>,
 }
 
 macro_rules! proto_versioned_functions_for_module {
     ($module:ident) => {
         HostModule {
             max_proto: $module::soroban_proto_any::get_max_proto(),
+            min_proto: $module::soroban_proto_any::get_min_proto(),
             get_soroban_version_info: $module::soroban_proto_any::get_soroban_version_info,
+            warmup: $module::soroban_proto_any::warmup,
+            get_invoke_nanos: $module::soroban_proto_any::get_invoke_nanos,
             invoke_host_function: $module::soroban_proto_any::invoke_host_function,
+            invoke_host_function_with_diagnostic_sink:
+                $module::soroban_proto_any::invoke_host_function_with_diagnostic_sink,
+            invoke_and_collect_events: $module::soroban_proto_any::invoke_and_collect_events,
             compute_transaction_resource_fee:
                 $module::soroban_proto_any::compute_transaction_resource_fee,
             compute_rent_fee: $module::soroban_proto_any::compute_rent_fee,
+            compute_rent_fee_itemized: $module::soroban_proto_any::compute_rent_fee_itemized,
+            estimate_worst_case_fee: $module::soroban_proto_any::estimate_worst_case_fee,
+            estimate_max_ttl_bump_fee: $module::soroban_proto_any::estimate_max_ttl_bump_fee,
             compute_rent_write_fee_per_1kb:
                 $module::soroban_proto_any::compute_rent_write_fee_per_1kb,
             contract_code_memory_size_for_rent:
                 $module::soroban_proto_any::contract_code_memory_size_for_rent,
+            compute_restore_footprint_fee:
+                $module::soroban_proto_any::compute_restore_footprint_fee,
+            get_supported_wasm_features: $module::soroban_proto_any::get_supported_wasm_features,
+            describe_cost_model: $module::soroban_proto_any::describe_cost_model,
+            ledger_entry_rent_bytes: $module::soroban_proto_any::ledger_entry_rent_bytes,
+            vm_instantiation_cost: $module::soroban_proto_any::vm_instantiation_cost,
             can_parse_transaction: $module::soroban_proto_any::can_parse_transaction,
+            is_soroban_transaction: $module::soroban_proto_any::is_soroban_transaction,
+            host_function_encoded_size: $module::soroban_proto_any::host_function_encoded_size,
+            host_function_type: $module::soroban_proto_any::host_function_type,
+            decode_ttl_entry: $module::soroban_proto_any::decode_ttl_entry,
+            decode_source_account: $module::soroban_proto_any::decode_source_account,
+            validate_footprint_disjoint: $module::soroban_proto_any::validate_footprint_disjoint,
+            validate_ttl_entry_correspondence:
+                $module::soroban_proto_any::validate_ttl_entry_correspondence,
+            auth_complexity: $module::soroban_proto_any::auth_complexity,
+            validate_contract_code_entry: $module::soroban_proto_any::validate_contract_code_entry,
+            extract_referenced_code_hashes:
+                $module::soroban_proto_any::extract_referenced_code_hashes,
+            invoked_contract_address: $module::soroban_proto_any::invoked_contract_address,
+            group_diagnostic_events_by_contract:
+                $module::soroban_proto_any::group_diagnostic_events_by_contract,
             #[cfg(feature = "testutils")]
             rustbuf_containing_scval_to_string:
                 $module::soroban_proto_any::rustbuf_containing_scval_to_string,
             #[cfg(feature = "testutils")]
             rustbuf_containing_diagnostic_event_to_string:
                 $module::soroban_proto_any::rustbuf_containing_diagnostic_event_to_string,
+            #[cfg(feature = "testutils")]
+            measure_marshalling_overhead: $module::soroban_proto_any::measure_marshalling_overhead,
+            #[cfg(feature = "testutils")]
+            serialize_invocation_inputs: $module::soroban_proto_any::serialize_invocation_inputs,
+            #[cfg(feature = "testutils")]
+            deserialize_invocation_inputs:
+                $module::soroban_proto_any::deserialize_invocation_inputs,
+            #[cfg(feature = "testutils")]
+            make_test_diagnostic_event: $module::soroban_proto_any::make_test_diagnostic_event,
+            #[cfg(feature = "testutils")]
+            summarize_auth_entry: $module::soroban_proto_any::summarize_auth_entry,
+            #[cfg(feature = "testutils")]
+            diff_contract_cost_params: $module::soroban_proto_any::diff_contract_cost_params,
         }
     };
 }
@@ -713,6 +1124,34 @@ const HOST_MODULES: &'static [HostModule] = &[
     proto_versioned_functions_for_module!(p23),
 ];
 
+// Returns the lowest ledger protocol that any linked host can execute.
+pub(crate) fn get_min_proto() -> u32 {
+    HOST_MODULES
+        .iter()
+        .map(|hm| hm.min_proto)
+        .min()
+        .unwrap_or_default()
+}
+
+// Checks that some linked host can execute ledgers of `protocol_version`, so
+// that catchup can refuse a ledger up front rather than failing mid-apply.
+pub(crate) fn can_apply_ledger(protocol_version: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let min_proto = get_min_proto();
+    let max_proto = HOST_MODULES
+        .iter()
+        .map(|hm| hm.max_proto)
+        .max()
+        .unwrap_or_default();
+    if protocol_version < min_proto || protocol_version > max_proto {
+        return Err(format!(
+            "protocol {} is outside the range {}..={} supported by the linked soroban hosts",
+            protocol_version, min_proto, max_proto
+        )
+        .into());
+    }
+    Ok(())
+}
+
 pub(crate) fn get_host_module_for_protocol(
     config_max_protocol: u32,
     ledger_protocol_version: u32,
@@ -748,4 +1187,33 @@ fn protocol_dispatches_as_expected() {
 
     // Ledger protocol has to be less than config max.
     assert!(get_host_module_for_protocol(20, 21).is_err());
+
+    // Nothing below the first soroban protocol.
+    assert_eq!(get_min_proto(), 20);
+
+    // One linked soroban per host module.
+    let linked_max_protos: Vec<u32> = list_linked_soroban_versions()
+        .iter()
+        .map(|v| v.env_max_proto)
+        .collect();
+    assert_eq!(linked_max_protos, vec![21, 22, 23]);
+}
+
+#[test]
+fn warmup_succeeds_on_current_host() {
+    let hm = get_host_module_for_protocol(
+        soroban_curr::soroban_proto_any::get_max_proto(),
+        soroban_curr::soroban_proto_any::get_max_proto(),
+    )
+    .unwrap();
+    (hm.warmup)().unwrap();
+}
+
+#[test]
+fn can_apply_ledger_checks_both_bounds() {
+    let max_proto = HOST_MODULES.last().unwrap().max_proto;
+    assert!(can_apply_ledger(get_min_proto()).is_ok());
+    assert!(can_apply_ledger(max_proto).is_ok());
+    assert!(can_apply_ledger(get_min_proto() - 1).is_err());
+    assert!(can_apply_ledger(max_proto + 1).is_err());
 }
@@ -5,13 +5,24 @@
 use crate::{
     log::partition::TX,
     rust_bridge::{
-        CxxBuf, CxxFeeConfiguration, CxxLedgerEntryRentChange, CxxLedgerInfo,
-        CxxRentFeeConfiguration, CxxRentWriteFeeConfiguration, CxxTransactionResources, FeePair,
-        InvokeHostFunctionOutput, RustBuf, SorobanVersionInfo, XDRFileHash,
+        CxxBuf, CxxFeeConfiguration, CxxInvokeHostFunctionOptions, CxxLedgerEntryRentChange,
+        CxxLedgerInfo, CxxRentFeeConfiguration, CxxRentWriteFeeConfiguration,
+        CxxTransactionResources, FeePair, HostErrorClass, InvokeHostFunctionOutput,
+        LedgerEntryChangeKind, RustBuf, SorobanVersionInfo, XDRFileHash,
     },
 };
 use log::{debug, error, trace, warn};
-use std::{fmt::Display, io::Cursor, panic, rc::Rc, time::Instant};
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::Cursor,
+    panic,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
+};
 
 // This module (soroban_proto_any) is bound to _multiple locations_ in the
 // module tree of this crate:
@@ -51,9 +62,10 @@ pub(crate) use super::soroban_env_host::{
     },
     xdr::{
         self, ContractCodeEntry, ContractCostParams, ContractEvent, ContractEventBody,
-        ContractEventType, ContractEventV0, DiagnosticEvent, ExtensionPoint, LedgerEntry,
-        LedgerEntryData, LedgerEntryExt, Limits, ReadXdr, ScError, ScErrorCode, ScErrorType,
-        ScSymbol, ScVal, TransactionEnvelope, TtlEntry, WriteXdr, XDR_FILES_SHA256,
+        ContractEventType, ContractEventV0, ContractExecutable, DiagnosticEvent, ExtensionPoint,
+        Hash, LedgerEntry, LedgerEntryData, LedgerEntryExt, LedgerKey, LedgerKeyTtl, Limits,
+        ReadXdr, ScError, ScErrorCode, ScErrorType, ScSymbol, ScVal, SorobanResources,
+        TransactionEnvelope, TtlEntry, WriteXdr, XDR_FILES_SHA256,
     },
     HostError, LedgerInfo, Val, VERSION,
 };
@@ -102,6 +114,9 @@ impl From<&CxxRentFeeConfiguration> for RentFeeConfiguration {
     }
 }
 
+// Size of the seed the host derives its per-invocation PRNG from.
+const BASE_PRNG_SEED_LEN: usize = 32;
+
 // FIXME: plumb this through from the limit xdrpp uses.
 // Currently they are just two same-valued constants.
 const MARSHALLING_STACK_LIMIT: u32 = 1000;
@@ -195,10 +210,28 @@ pub fn get_xdr_hashes() -> Vec<XDRFileHash> {
         .collect()
 }
 
+// A single fingerprint of all the XDR definitions: the SHA-256 of the
+// concatenation of every (file, hash) pair in `XDR_FILES_SHA256`, sorted by
+// file name. Equal digests imply identical XDR definitions.
+pub fn get_xdr_definitions_digest() -> [u8; 32] {
+    let mut pairs = XDR_FILES_SHA256.to_vec();
+    pairs.sort();
+    let mut hasher = Sha256::new();
+    for (file, hash) in pairs {
+        hasher.update(file.as_bytes());
+        hasher.update(hash.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
 pub const fn get_max_proto() -> u32 {
     super::get_version_protocol(&VERSION)
 }
 
+pub const fn get_min_proto() -> u32 {
+    super::MIN_LEDGER_PROTOCOL_VERSION
+}
+
 pub fn get_soroban_version_info(core_max_proto: u32) -> SorobanVersionInfo {
     let env_max_proto = get_max_proto();
     let xdr_base_git_rev = match VERSION.xdr.xdr {
@@ -236,6 +269,7 @@ pub fn get_soroban_version_info(core_max_proto: u32) -> SorobanVersionInfo {
         xdr_git_rev: VERSION.xdr.rev.to_string(),
         xdr_base_git_rev,
         xdr_file_hashes: get_xdr_hashes(),
+        xdr_definitions_digest: get_xdr_definitions_digest().to_vec(),
     }
 }
 
@@ -245,29 +279,131 @@ fn log_diagnostic_events(events: &Vec<DiagnosticEvent>) {
     }
 }
 
+// Encodes each diagnostic event and hands it to `emit` as soon as it's
+// encoded, so a consumer that processes events one at a time never has to hold
+// the whole encoded set in memory. Events that fail to encode are skipped.
+fn encode_diagnostic_events_with(events: &Vec<DiagnosticEvent>, mut emit: impl FnMut(RustBuf)) {
+    for e in events {
+        if let Ok(encoded) = non_metered_xdr_to_rust_buf(e) {
+            emit(encoded);
+        }
+    }
+}
+
 fn encode_diagnostic_events(events: &Vec<DiagnosticEvent>) -> Vec<RustBuf> {
-    events
-        .iter()
-        .filter_map(|e| {
-            if let Ok(encoded) = non_metered_xdr_to_rust_buf(e) {
-                Some(encoded)
-            } else {
-                None
-            }
-        })
-        .collect()
+    let mut encoded = Vec::with_capacity(events.len());
+    encode_diagnostic_events_with(events, |buf| encoded.push(buf));
+    encoded
+}
+
+// Concatenates `bufs` into one buffer, each prefixed with its length as an
+// 8-byte big-endian integer, for the `pack_events` invocation option.
+fn pack_buffers(bufs: Vec<RustBuf>) -> RustBuf {
+    let mut packed = Vec::with_capacity(bufs.iter().map(|buf| 8 + buf.data.len()).sum());
+    for buf in bufs {
+        packed.extend_from_slice(&(buf.data.len() as u64).to_be_bytes());
+        packed.extend_from_slice(&buf.data);
+    }
+    packed.into()
+}
+
+// Tracks the encoded size of an invocation's output against the
+// `max_output_bytes` invocation option (0 meaning unbounded), so that building
+// the output can stop as soon as the limit is crossed.
+struct OutputSizeLimit {
+    max_bytes: u64,
+    bytes: u64,
+}
+
+impl OutputSizeLimit {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    fn add(&mut self, bytes: usize) {
+        self.bytes = self.bytes.saturating_add(bytes as u64);
+    }
+
+    fn exceeded(&self) -> bool {
+        self.max_bytes != 0 && self.bytes > self.max_bytes
+    }
+
+    fn error(&self) -> HostError {
+        debug!(target: TX, "invocation output of at least {} bytes exceeds limit of {} bytes",
+            self.bytes, self.max_bytes);
+        (ScErrorType::Budget, ScErrorCode::ExceededLimit).into()
+    }
 }
 
+// Encodes diagnostic events like `encode_diagnostic_events`, adding their sizes
+// to `output_size` and stopping as soon as it's exceeded.
+fn encode_diagnostic_events_within(
+    events: &Vec<DiagnosticEvent>,
+    output_size: &mut OutputSizeLimit,
+) -> Vec<RustBuf> {
+    let mut encoded = vec![];
+    for e in events {
+        if output_size.exceeded() {
+            break;
+        }
+        if let Ok(buf) = non_metered_xdr_to_rust_buf(e) {
+            output_size.add(buf.data.len());
+            encoded.push(buf);
+        }
+    }
+    encoded
+}
+
+// The ledger effects of an invocation, as returned in `InvokeHostFunctionOutput`.
+struct LedgerEffects {
+    modified_entries: Vec<RustBuf>,
+    modified_entry_kinds: Vec<LedgerEntryChangeKind>,
+    deleted_keys: Vec<RustBuf>,
+    // Whether no entry was created, updated or deleted, TTL extensions aside.
+    read_only: bool,
+}
+
+// Returns the modified entries (and deleted keys) sorted by their encoded ledger
+// key, so that the output does not depend on the order in which the host
+// reports changes. Written entries that were passed in live and whose new value
+// is among `unchanged_values` are left out; restored entries are always
+// returned, since core has to write them back even if the invocation didn't
+// change them. `existed` tells whether the entry of a change was passed to
+// the invocation as a live entry: the host reports expired entries as
+// missing, so its old entry size alone can't tell.
 fn extract_ledger_effects(
     entry_changes: Vec<LedgerEntryChange>,
-) -> Result<Vec<RustBuf>, HostError> {
-    let mut modified_entries = vec![];
+    unchanged_values: &HashSet<&[u8]>,
+    mut existed: impl FnMut(&LedgerEntryChange) -> bool,
+) -> Result<LedgerEffects, HostError> {
+    let mut modified_entries: Vec<(Vec<u8>, RustBuf, LedgerEntryChangeKind)> = vec![];
+    let mut deleted_keys: Vec<Vec<u8>> = vec![];
+    let mut read_only = true;
 
     for change in entry_changes {
+        let existed = existed(&change);
         // Extract ContractCode and ContractData entry changes first
         if !change.read_only {
-            if let Some(encoded_new_value) = change.encoded_new_value {
-                modified_entries.push(encoded_new_value.into());
+            match change.encoded_new_value {
+                Some(encoded_new_value)
+                    if existed && unchanged_values.contains(encoded_new_value.as_slice()) => {}
+                Some(encoded_new_value) => {
+                    let kind = if existed {
+                        LedgerEntryChangeKind::Updated
+                    } else {
+                        LedgerEntryChangeKind::Created
+                    };
+                    modified_entries.push((change.encoded_key, encoded_new_value.into(), kind));
+                    read_only = false;
+                }
+                None if existed => {
+                    deleted_keys.push(change.encoded_key);
+                    read_only = false;
+                }
+                None => (),
             }
         }
 
@@ -290,55 +426,265 @@ fn extract_ledger_effects(
                     }),
                     ext: LedgerEntryExt::V0,
                 };
+                let key = LedgerKey::Ttl(LedgerKeyTtl {
+                    key_hash: hash_bytes.into(),
+                });
 
                 let encoded = non_metered_xdr_to_rust_buf(&le)
                     .map_err(|_| (ScErrorType::Value, ScErrorCode::InternalError))?;
-                modified_entries.push(encoded);
+                let encoded_key = non_metered_xdr_to_vec(&key)
+                    .map_err(|_| (ScErrorType::Value, ScErrorCode::InternalError))?;
+                let kind = if existed {
+                    LedgerEntryChangeKind::TtlBumped
+                } else {
+                    LedgerEntryChangeKind::Created
+                };
+                modified_entries.push((encoded_key, encoded, kind));
             }
         }
     }
 
-    Ok(modified_entries)
+    modified_entries.sort_by(|a, b| a.0.cmp(&b.0));
+    deleted_keys.sort();
+    let (modified_entries, modified_entry_kinds) = modified_entries
+        .into_iter()
+        .map(|(_, entry, kind)| (entry, kind))
+        .unzip();
+    Ok(LedgerEffects {
+        modified_entries,
+        modified_entry_kinds,
+        deleted_keys: deleted_keys.into_iter().map(RustBuf::from).collect(),
+        read_only,
+    })
+}
+
+// Returns the TTL key hashes of the encoded `TtlEntry`s passed to an
+// invocation. These are read straight off the buffers, whose XDR starts with
+// the 32-byte key hash, rather than decoded: this runs on the apply path, and
+// would otherwise be unmetered work.
+fn input_ttl_key_hashes<'a>(ttl_entries: impl Iterator<Item = &'a [u8]>) -> HashSet<&'a [u8]> {
+    ttl_entries.filter_map(|buf| buf.get(..32)).collect()
+}
+
+// Whether the entry of `change` was passed to the invocation as a live entry.
+// Only entries with a TTL can expire, so for those the host's old entry size
+// can misreport it, and `is_input_ttl_key_hash` tells whether the entry was
+// passed in. With auto-restoration, the host reports every live entry it was
+// passed with its old size, and leaves it out only for the restored ones, which
+// core passes in as well: so when `any_restored` is set, an entry without an
+// old size was not live.
+fn passed_in_live(
+    change: &LedgerEntryChange,
+    any_restored: bool,
+    is_input_ttl_key_hash: impl FnOnce(&[u8]) -> bool,
+) -> bool {
+    if super::ledger_entry_change_old_size(change) > 0 {
+        return true;
+    }
+    match &change.ttl_change {
+        Some(ttl_change) if !any_restored => is_input_ttl_key_hash(&ttl_change.key_hash),
+        _ => false,
+    }
+}
+
+// Checks that none of the encoded contract events has more than `max_topics`
+// topics. The host itself doesn't bound the number of topics, so a violation is
+// reported as an internal error.
+fn validate_event_topic_limits(
+    encoded_contract_events: &[RustBuf],
+    max_topics: u32,
+) -> Result<(), HostError> {
+    for buf in encoded_contract_events {
+        let event = ContractEvent::read_xdr(&mut xdr::Limited::new(
+            Cursor::new(buf.data.as_slice()),
+            Limits {
+                depth: MARSHALLING_STACK_LIMIT,
+                len: buf.data.len(),
+            },
+        ))
+        .map_err(|_| (ScErrorType::Events, ScErrorCode::InternalError))?;
+        let ContractEventBody::V0(body) = event.body;
+        if body.topics.len() > max_topics as usize {
+            error!(target: TX, "contract event has {} topics, more than the maximum of {}",
+                body.topics.len(), max_topics);
+            return Err((ScErrorType::Events, ScErrorCode::InternalError).into());
+        }
+    }
+    Ok(())
+}
+
+// Returns the encoded keys of all entries the invocation accessed (read-only
+// or not), sorted.
+fn extract_touched_keys(entry_changes: &[LedgerEntryChange]) -> Vec<RustBuf> {
+    let mut keys: Vec<&Vec<u8>> = entry_changes.iter().map(|c| &c.encoded_key).collect();
+    keys.sort();
+    keys.into_iter().map(|k| RustBuf::from(k.clone())).collect()
 }
 
 /// Deserializes an [`xdr::HostFunction`] host function XDR object an
 /// [`xdr::Footprint`] and a sequence of [`xdr::LedgerEntry`] entries containing all
 /// the data the invocation intends to read. Then calls the specified host function
 /// and returns the [`InvokeHostFunctionOutput`] that contains the host function
-/// result, events and modified ledger entries. Ledger entries not returned have
-/// been deleted.
+/// result, events and modified ledger entries, along with the keys of the
+/// ledger entries that have been deleted.
 pub(crate) fn invoke_host_function(
     enable_diagnostics: bool,
     instruction_limit: u32,
     hf_buf: &CxxBuf,
     resources_buf: &CxxBuf,
-    restored_rw_entry_indices: &Vec<u32>,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &crate::SorobanModuleCache,
+) -> Result<InvokeHostFunctionOutput, Box<dyn Error>> {
+    invoke_host_function_with_optional_sink(
+        enable_diagnostics,
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        rent_fee_configuration,
+        options,
+        module_cache,
+        None,
+    )
+}
+
+/// Runs a host function like [`invoke_host_function`] with diagnostics
+/// enabled, but passes each encoded diagnostic event to `diagnostic_sink`
+/// instead of collecting them into the output's `diagnostic_events`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn invoke_host_function_with_diagnostic_sink(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &crate::SorobanModuleCache,
+    diagnostic_sink: &mut dyn FnMut(RustBuf),
+) -> Result<InvokeHostFunctionOutput, Box<dyn Error>> {
+    invoke_host_function_with_optional_sink(
+        true,
+        instruction_limit,
+        hf_buf,
+        resources_buf,
+        restored_rw_entry_indices,
+        source_account_buf,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        ttl_entries,
+        base_prng_seed,
+        rent_fee_configuration,
+        options,
+        module_cache,
+        Some(diagnostic_sink),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn invoke_host_function_with_optional_sink(
+    enable_diagnostics: bool,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
     source_account_buf: &CxxBuf,
-    auth_entries: &Vec<CxxBuf>,
+    auth_entries: &[CxxBuf],
     ledger_info: &CxxLedgerInfo,
-    ledger_entries: &Vec<CxxBuf>,
-    ttl_entries: &Vec<CxxBuf>,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
     base_prng_seed: &CxxBuf,
     rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
     module_cache: &crate::SorobanModuleCache,
+    diagnostic_sink: Option<&mut dyn FnMut(RustBuf)>,
 ) -> Result<InvokeHostFunctionOutput, Box<dyn Error>> {
-    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+    if options.max_allowed_instructions != 0
+        && instruction_limit as u64 > options.max_allowed_instructions
+    {
+        return Err(CoreHostError::General(format!(
+            "instruction limit {} exceeds maximum allowed {}",
+            instruction_limit, options.max_allowed_instructions
+        ))
+        .into());
+    }
+    let mut host_ledger_info: LedgerInfo = ledger_info.try_into()?;
+    if cfg!(feature = "testutils") && options.base_reserve_override != 0 {
+        host_ledger_info.base_reserve = options.base_reserve_override;
+    }
+    #[cfg(feature = "version-timings")]
+    let start = Instant::now();
+    let mut res = catch_host_panic(|| {
         invoke_host_function_or_maybe_panic(
             enable_diagnostics,
             instruction_limit,
             hf_buf,
             resources_buf,
-            restored_rw_entry_indices.as_slice(),
+            restored_rw_entry_indices,
             source_account_buf,
             auth_entries,
             ledger_info,
+            host_ledger_info,
             ledger_entries,
             ttl_entries,
             base_prng_seed,
             rent_fee_configuration,
+            options,
             module_cache,
+            diagnostic_sink,
         )
-    }));
+    });
+    #[cfg(feature = "version-timings")]
+    INVOKE_NANOS.fetch_add(
+        u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX),
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    if options.pack_events {
+        if let Ok(output) = &mut res {
+            output.packed_contract_events =
+                pack_buffers(std::mem::take(&mut output.contract_events));
+            output.packed_diagnostic_events =
+                pack_buffers(std::mem::take(&mut output.diagnostic_events));
+        }
+    }
+    res
+}
+
+// Cumulative wall-clock time spent in `invoke_host_function` on this host.
+// Each versioned copy of this module has its own counter.
+#[cfg(feature = "version-timings")]
+static INVOKE_NANOS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+pub(crate) fn get_invoke_nanos() -> u64 {
+    #[cfg(feature = "version-timings")]
+    return INVOKE_NANOS.load(std::sync::atomic::Ordering::Relaxed);
+    #[cfg(not(feature = "version-timings"))]
+    0
+}
+
+// Runs `f`, converting any (unwind-able) panic in the host into an error.
+fn catch_host_panic<T>(f: impl FnOnce() -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(f));
     match res {
         Err(r) => {
             if let Some(s) = r.downcast_ref::<String>() {
@@ -353,7 +699,116 @@ pub(crate) fn invoke_host_function(
     }
 }
 
-fn make_trace_hook_fn<'a>() -> super::soroban_env_host::TraceHook {
+/// Runs a host function like [`invoke_host_function`], but only returns the
+/// contract events it emitted, failing if the invocation fails. Diagnostics,
+/// ledger effects and rent fees are not computed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn invoke_and_collect_events(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    module_cache: &crate::SorobanModuleCache,
+) -> Result<Vec<RustBuf>, Box<dyn Error>> {
+    catch_host_panic(|| {
+        let budget = prepare_invocation(
+            instruction_limit as u64,
+            ledger_info.memory_limit as u64,
+            restored_rw_entry_indices,
+            ledger_info,
+            base_prng_seed,
+        )?;
+        let mut diagnostic_events = vec![];
+        let res = super::invoke_host_function_with_trace_hook_and_module_cache(
+            &budget,
+            false,
+            hf_buf,
+            resources_buf,
+            restored_rw_entry_indices,
+            source_account_buf,
+            auth_entries.iter(),
+            ledger_info.try_into()?,
+            ledger_entries.iter(),
+            ttl_entries.iter(),
+            base_prng_seed,
+            &mut diagnostic_events,
+            None,
+            module_cache,
+        )
+        .map_err(CoreHostError::from)?;
+        res.encoded_invoke_result.map_err(CoreHostError::from)?;
+        Ok(res
+            .encoded_contract_events
+            .into_iter()
+            .map(RustBuf::from)
+            .collect())
+    })
+}
+
+// Uploads a small built-in contract through the host's end-to-end invocation
+// path, to initialize the host's lazily-initialized state before real traffic
+// arrives. The invocation runs against a synthetic ledger (with the host's
+// default budget) and a throwaway module cache, and its effects are dropped.
+pub(crate) fn warmup() -> Result<(), Box<dyn Error>> {
+    catch_host_panic(|| {
+        let wasm = soroban_test_wasms::ADD_I32;
+        let code_key = LedgerKey::ContractCode(xdr::LedgerKeyContractCode {
+            hash: Hash(Sha256::digest(wasm).into()),
+        });
+        let resources = super::make_soroban_resources(
+            xdr::LedgerFootprint {
+                read_only: vec![].try_into()?,
+                read_write: vec![code_key].try_into()?,
+            },
+            10_000_000,
+            0,
+            10_000,
+        );
+        let source_account =
+            xdr::AccountId(xdr::PublicKey::PublicKeyTypeEd25519(xdr::Uint256([0; 32])));
+        let ledger_info = LedgerInfo {
+            protocol_version: get_max_proto(),
+            sequence_number: 1,
+            timestamp: 0,
+            network_id: [0; 32],
+            base_reserve: 0,
+            min_temp_entry_ttl: 16,
+            min_persistent_entry_ttl: 4096,
+            max_entry_ttl: 6_312_000,
+        };
+        let module_cache = crate::soroban_module_cache::new_module_cache()?;
+        let mut diagnostic_events = vec![];
+        let res = super::invoke_host_function_with_trace_hook_and_module_cache(
+            &Budget::default(),
+            false,
+            non_metered_xdr_to_vec(&xdr::HostFunction::UploadContractWasm(wasm.try_into()?))?,
+            non_metered_xdr_to_vec(&resources)?,
+            &[],
+            non_metered_xdr_to_vec(&source_account)?,
+            Vec::<Vec<u8>>::new().into_iter(),
+            ledger_info,
+            Vec::<Vec<u8>>::new().into_iter(),
+            Vec::<Vec<u8>>::new().into_iter(),
+            vec![0; BASE_PRNG_SEED_LEN],
+            &mut diagnostic_events,
+            None,
+            &module_cache,
+        )
+        .map_err(CoreHostError::from)?;
+        res.encoded_invoke_result.map_err(CoreHostError::from)?;
+        Ok(())
+    })
+}
+
+// Makes a trace hook that describes each host trace event as a line of text
+// and passes it to `emit`.
+fn make_trace_hook_fn(emit: impl Fn(String) + 'static) -> super::soroban_env_host::TraceHook {
     let prev_state = std::cell::RefCell::new(String::new());
     Rc::new(move |host, traceevent| {
         if traceevent.is_begin() || traceevent.is_end() {
@@ -363,14 +818,14 @@ fn make_trace_hook_fn<'a>() -> super::soroban_env_host::TraceHook {
             Ok(tr) => {
                 let state_str = format!("{}", tr.state);
                 if prev_state.borrow().is_empty() {
-                    trace!(target: TX, "{}: {}", tr.event, state_str);
+                    emit(format!("{}: {}", tr.event, state_str));
                 } else {
                     let diff = crate::log::diff_line(&prev_state.borrow(), &state_str);
-                    trace!(target: TX, "{}: {}", tr.event, diff);
+                    emit(format!("{}: {}", tr.event, diff));
                 }
                 prev_state.replace(state_str);
             }
-            Err(e) => trace!(target: TX, "{}", e),
+            Err(e) => emit(format!("{}", e)),
         }
         Ok(())
     })
@@ -388,47 +843,732 @@ fn encode_contract_cost_params(params: &ContractCostParams) -> Result<RustBuf, B
     Ok(non_metered_xdr_to_rust_buf(params)?)
 }
 
-fn invoke_host_function_or_maybe_panic(
-    enable_diagnostics: bool,
+// Compares two encoded ContractCostParams entry-by-entry and returns one line
+// per cost type whose terms differ, e.g. "VmInstantiation: const 100->120,
+// linear 5->5". Entries present on only one side are reported as added or
+// removed.
+#[cfg(feature = "testutils")]
+pub(crate) fn diff_contract_cost_params(
+    old: &CxxBuf,
+    new: &CxxBuf,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(diff_decoded_cost_params(
+        &decode_contract_cost_params(old)?,
+        &decode_contract_cost_params(new)?,
+    ))
+}
+
+#[cfg(feature = "testutils")]
+fn diff_decoded_cost_params(old: &ContractCostParams, new: &ContractCostParams) -> Vec<String> {
+    let cost_type_name = |i: usize| match xdr::ContractCostType::VARIANTS_STR.get(i) {
+        Some(name) => name.to_string(),
+        None => format!("ContractCostType({i})"),
+    };
+    let mut diffs = Vec::new();
+    for i in 0..old.0.len().max(new.0.len()) {
+        match (old.0.get(i), new.0.get(i)) {
+            (Some(o), Some(n)) => {
+                if o.const_term != n.const_term || o.linear_term != n.linear_term {
+                    diffs.push(format!(
+                        "{}: const {}->{}, linear {}->{}",
+                        cost_type_name(i),
+                        o.const_term,
+                        n.const_term,
+                        o.linear_term,
+                        n.linear_term
+                    ));
+                }
+            }
+            (Some(o), None) => diffs.push(format!(
+                "{}: removed (was const {}, linear {})",
+                cost_type_name(i),
+                o.const_term,
+                o.linear_term
+            )),
+            (None, Some(n)) => diffs.push(format!(
+                "{}: added (const {}, linear {})",
+                cost_type_name(i),
+                n.const_term,
+                n.linear_term
+            )),
+            (None, None) => (),
+        }
+    }
+    diffs
+}
+
+// Names of the optional wasm proposals this host accepts contracts using.
+pub(crate) fn get_supported_wasm_features() -> Vec<String> {
+    super::SUPPORTED_WASM_FEATURES
+        .iter()
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+// Describes the cost model given by encoded cpu and memory ContractCostParams
+// with one line per cost type this host knows, e.g. "VmInstantiation[6]: cpu
+// const=X linear=Y, mem const=Z linear=W". A cost type without an entry in
+// one of the params is reported as missing there.
+pub(crate) fn describe_cost_model(
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let cpu = non_metered_xdr_from_cxx_buf::<ContractCostParams>(cpu_cost_params)?;
+    let mem = non_metered_xdr_from_cxx_buf::<ContractCostParams>(mem_cost_params)?;
+    let describe = |params: &ContractCostParams, i: usize| match params.0.get(i) {
+        Some(p) => format!("const={} linear={}", p.const_term, p.linear_term),
+        None => "missing".to_string(),
+    };
+    Ok(xdr::ContractCostType::VARIANTS_STR
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            format!(
+                "{}[{}]: cpu {}, mem {}",
+                name,
+                i,
+                describe(&cpu, i),
+                describe(&mem, i)
+            )
+        })
+        .collect())
+}
+
+// Decodes every input of an invocation and encodes a synthetic output of the
+// same shape as a successful one (each ledger entry written back, plus a void
+// result), without running the host. Returns the nanoseconds spent, which
+// approximates the marshalling share of `time_nsecs`.
+#[cfg(feature = "testutils")]
+pub(crate) fn measure_marshalling_overhead(
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+) -> Result<u64, Box<dyn Error>> {
+    let start_time = Instant::now();
+    let _host_function: xdr::HostFunction = non_metered_xdr_from_cxx_buf(hf_buf)?;
+    let _resources: SorobanResources = non_metered_xdr_from_cxx_buf(resources_buf)?;
+    let _source_account: xdr::AccountId = non_metered_xdr_from_cxx_buf(source_account_buf)?;
+    for buf in auth_entries {
+        let _: xdr::SorobanAuthorizationEntry = non_metered_xdr_from_cxx_buf(buf)?;
+    }
+    for buf in ttl_entries {
+        // TTL entries of entries that don't have one are passed empty.
+        if !buf.data.is_empty() {
+            let _: TtlEntry = non_metered_xdr_from_cxx_buf(buf)?;
+        }
+    }
+    let mut encoded: Vec<RustBuf> = Vec::with_capacity(ledger_entries.len() + 1);
+    for buf in ledger_entries {
+        let entry: LedgerEntry = non_metered_xdr_from_cxx_buf(buf)?;
+        encoded.push(non_metered_xdr_to_rust_buf(&entry)?);
+    }
+    encoded.push(non_metered_xdr_to_rust_buf(&ScVal::Void)?);
+    let time_nsecs = start_time.elapsed().as_nanos() as u64;
+    drop(encoded);
+    Ok(time_nsecs)
+}
+
+// Describes an encoded `SorobanAuthorizationEntry` for debugging: the kind of
+// credentials, how many signatures they carry and the root invocation the
+// entry authorizes.
+#[cfg(feature = "testutils")]
+pub(crate) fn summarize_auth_entry(buf: &CxxBuf) -> Result<String, Box<dyn Error>> {
+    let entry: xdr::SorobanAuthorizationEntry = non_metered_xdr_from_cxx_buf(buf)?;
+    let credentials = match &entry.credentials {
+        xdr::SorobanCredentials::SourceAccount => "source account".to_string(),
+        xdr::SorobanCredentials::Address(creds) => {
+            // Account signatures are a vector of signature maps; an empty
+            // signature is void.
+            let signature_count = match &creds.signature {
+                ScVal::Void => 0,
+                ScVal::Vec(Some(sigs)) => sigs.len(),
+                _ => 1,
+            };
+            format!(
+                "address {} (nonce {}, expires at ledger {}, {} signature(s))",
+                creds.address, creds.nonce, creds.signature_expiration_ledger, signature_count
+            )
+        }
+    };
+    let root = &entry.root_invocation;
+    let function = if let xdr::SorobanAuthorizedFunction::ContractFn(args) = &root.function {
+        format!("{}::{}", args.contract_address, args.function_name.0)
+    } else {
+        "contract creation".to_string()
+    };
+    Ok(format!(
+        "credentials: {}; root invocation: {} with {} sub-invocation(s)",
+        credentials,
+        function,
+        root.sub_invocations.len()
+    ))
+}
+
+// Version of the layout of the bundle written by `serialize_invocation_inputs`.
+#[cfg(feature = "testutils")]
+const INVOCATION_INPUTS_BUNDLE_VERSION: u32 = 1;
+
+#[cfg(feature = "testutils")]
+fn bundle_symbol(name: &str) -> Result<ScVal, Box<dyn Error>> {
+    Ok(ScVal::Symbol(ScSymbol(
+        name.try_into().map_err(CoreHostError::from)?,
+    )))
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_bytes(data: &[u8]) -> Result<ScVal, Box<dyn Error>> {
+    Ok(ScVal::Bytes(xdr::ScBytes(
+        data.try_into().map_err(CoreHostError::from)?,
+    )))
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_vec(vals: Vec<ScVal>) -> Result<ScVal, Box<dyn Error>> {
+    Ok(ScVal::Vec(Some(xdr::ScVec(
+        vals.try_into().map_err(CoreHostError::from)?,
+    ))))
+}
+
+// Fields are passed in symbol order, so that the map is sorted like the host
+// expects of an `ScMap`.
+#[cfg(feature = "testutils")]
+fn bundle_map(fields: Vec<(&str, ScVal)>) -> Result<ScVal, Box<dyn Error>> {
+    let mut entries = Vec::with_capacity(fields.len());
+    for (name, val) in fields {
+        entries.push(xdr::ScMapEntry {
+            key: bundle_symbol(name)?,
+            val,
+        });
+    }
+    Ok(ScVal::Map(Some(xdr::ScMap(
+        entries.try_into().map_err(CoreHostError::from)?,
+    ))))
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field<'a>(map: &'a ScVal, name: &str) -> Result<&'a ScVal, Box<dyn Error>> {
+    let ScVal::Map(Some(map)) = map else {
+        return Err(CoreHostError::General("invocation bundle field is not a map".into()).into());
+    };
+    map.iter()
+        .find(
+            |entry| matches!(&entry.key, ScVal::Symbol(sym) if sym.0.as_slice() == name.as_bytes()),
+        )
+        .map(|entry| &entry.val)
+        .ok_or_else(|| {
+            CoreHostError::General(format!("invocation bundle is missing {}", name)).into()
+        })
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_bytes(map: &ScVal, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    match bundle_field(map, name)? {
+        ScVal::Bytes(bytes) => Ok(bytes.to_vec()),
+        _ => Err(CoreHostError::General(format!("invocation bundle {} is not bytes", name)).into()),
+    }
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_vec<'a>(map: &'a ScVal, name: &str) -> Result<&'a [ScVal], Box<dyn Error>> {
+    match bundle_field(map, name)? {
+        ScVal::Vec(Some(vec)) => Ok(vec.as_slice()),
+        _ => Err(CoreHostError::General(format!("invocation bundle {} is not a vec", name)).into()),
+    }
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_bytes_vec(map: &ScVal, name: &str) -> Result<Vec<RustBuf>, Box<dyn Error>> {
+    bundle_field_vec(map, name)?
+        .iter()
+        .map(|val| match val {
+            ScVal::Bytes(bytes) => Ok(RustBuf::from(bytes.to_vec())),
+            _ => Err(
+                CoreHostError::General(format!("invocation bundle {} holds non-bytes", name))
+                    .into(),
+            ),
+        })
+        .collect()
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_u32(map: &ScVal, name: &str) -> Result<u32, Box<dyn Error>> {
+    match bundle_field(map, name)? {
+        ScVal::U32(v) => Ok(*v),
+        _ => Err(CoreHostError::General(format!("invocation bundle {} is not a u32", name)).into()),
+    }
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_u64(map: &ScVal, name: &str) -> Result<u64, Box<dyn Error>> {
+    match bundle_field(map, name)? {
+        ScVal::U64(v) => Ok(*v),
+        _ => Err(CoreHostError::General(format!("invocation bundle {} is not a u64", name)).into()),
+    }
+}
+
+#[cfg(feature = "testutils")]
+fn bundle_field_i64(map: &ScVal, name: &str) -> Result<i64, Box<dyn Error>> {
+    match bundle_field(map, name)? {
+        ScVal::I64(v) => Ok(*v),
+        _ => {
+            Err(CoreHostError::General(format!("invocation bundle {} is not an i64", name)).into())
+        }
+    }
+}
+
+// Packages the inputs of an `invoke_host_function` call into one encoded
+// `ScVal` map keyed by input name, so that a failing invocation can be
+// captured and replayed offline with `deserialize_invocation_inputs`.
+#[cfg(feature = "testutils")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn serialize_invocation_inputs(
     instruction_limit: u32,
     hf_buf: &CxxBuf,
     resources_buf: &CxxBuf,
     restored_rw_entry_indices: &[u32],
     source_account_buf: &CxxBuf,
-    auth_entries: &Vec<CxxBuf>,
+    auth_entries: &[CxxBuf],
     ledger_info: &CxxLedgerInfo,
-    ledger_entries: &Vec<CxxBuf>,
-    ttl_entries: &Vec<CxxBuf>,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
     base_prng_seed: &CxxBuf,
     rent_fee_configuration: &CxxRentFeeConfiguration,
-    module_cache: &crate::SorobanModuleCache,
-) -> Result<InvokeHostFunctionOutput, Box<dyn Error>> {
-    #[cfg(feature = "tracy")]
-    let client = tracy_client::Client::start();
-    let _span0 = tracy_span!("invoke_host_function_or_maybe_panic");
+) -> Result<RustBuf, Box<dyn Error>> {
+    let buf = |buf: &CxxBuf| RustBuf::from(buf.data.as_slice().to_vec());
+    let bufs = |bufs: &[CxxBuf]| bufs.iter().map(buf).collect();
+    encode_invocation_inputs(&crate::InvocationInputsBundle {
+        instruction_limit,
+        hf: buf(hf_buf),
+        resources: buf(resources_buf),
+        restored_rw_entry_indices: restored_rw_entry_indices.to_vec(),
+        source_account: buf(source_account_buf),
+        auth_entries: bufs(auth_entries),
+        protocol_version: ledger_info.protocol_version,
+        sequence_number: ledger_info.sequence_number,
+        timestamp: ledger_info.timestamp,
+        network_id: ledger_info.network_id.clone(),
+        base_reserve: ledger_info.base_reserve,
+        memory_limit: ledger_info.memory_limit,
+        min_temp_entry_ttl: ledger_info.min_temp_entry_ttl,
+        min_persistent_entry_ttl: ledger_info.min_persistent_entry_ttl,
+        max_entry_ttl: ledger_info.max_entry_ttl,
+        cpu_cost_params: buf(&ledger_info.cpu_cost_params),
+        mem_cost_params: buf(&ledger_info.mem_cost_params),
+        ledger_entries: bufs(ledger_entries),
+        ttl_entries: bufs(ttl_entries),
+        base_prng_seed: buf(base_prng_seed),
+        rent_fee_configuration: rent_fee_configuration.clone(),
+    })
+}
 
-    let protocol_version = ledger_info.protocol_version;
+#[cfg(feature = "testutils")]
+fn encode_invocation_inputs(
+    inputs: &crate::InvocationInputsBundle,
+) -> Result<RustBuf, Box<dyn Error>> {
+    let bufs = |bufs: &Vec<RustBuf>| -> Result<ScVal, Box<dyn Error>> {
+        bundle_vec(
+            bufs.iter()
+                .map(|buf| bundle_bytes(buf.data.as_slice()))
+                .collect::<Result<_, _>>()?,
+        )
+    };
+    let ledger_info = bundle_map(vec![
+        ("base_reserve", ScVal::U32(inputs.base_reserve)),
+        (
+            "cpu_cost_params",
+            bundle_bytes(inputs.cpu_cost_params.data.as_slice())?,
+        ),
+        ("max_entry_ttl", ScVal::U32(inputs.max_entry_ttl)),
+        (
+            "mem_cost_params",
+            bundle_bytes(inputs.mem_cost_params.data.as_slice())?,
+        ),
+        ("memory_limit", ScVal::U32(inputs.memory_limit)),
+        (
+            "min_persistent_entry_ttl",
+            ScVal::U32(inputs.min_persistent_entry_ttl),
+        ),
+        ("min_temp_entry_ttl", ScVal::U32(inputs.min_temp_entry_ttl)),
+        ("network_id", bundle_bytes(&inputs.network_id)?),
+        ("protocol_version", ScVal::U32(inputs.protocol_version)),
+        ("sequence_number", ScVal::U32(inputs.sequence_number)),
+        ("timestamp", ScVal::U64(inputs.timestamp)),
+    ])?;
+    let rent_fee_configuration = &inputs.rent_fee_configuration;
+    let rent_fee_configuration = bundle_map(vec![
+        (
+            "fee_per_rent_1kb",
+            ScVal::I64(rent_fee_configuration.fee_per_rent_1kb),
+        ),
+        (
+            "fee_per_write_1kb",
+            ScVal::I64(rent_fee_configuration.fee_per_write_1kb),
+        ),
+        (
+            "fee_per_write_entry",
+            ScVal::I64(rent_fee_configuration.fee_per_write_entry),
+        ),
+        (
+            "persistent_rent_rate_denominator",
+            ScVal::I64(rent_fee_configuration.persistent_rent_rate_denominator),
+        ),
+        (
+            "temporary_rent_rate_denominator",
+            ScVal::I64(rent_fee_configuration.temporary_rent_rate_denominator),
+        ),
+    ])?;
+    let bundle = bundle_map(vec![
+        ("auth_entries", bufs(&inputs.auth_entries)?),
+        (
+            "base_prng_seed",
+            bundle_bytes(inputs.base_prng_seed.data.as_slice())?,
+        ),
+        ("hf", bundle_bytes(inputs.hf.data.as_slice())?),
+        ("instruction_limit", ScVal::U32(inputs.instruction_limit)),
+        ("ledger_entries", bufs(&inputs.ledger_entries)?),
+        ("ledger_info", ledger_info),
+        ("rent_fee_configuration", rent_fee_configuration),
+        ("resources", bundle_bytes(inputs.resources.data.as_slice())?),
+        (
+            "restored_rw_entry_indices",
+            bundle_vec(
+                inputs
+                    .restored_rw_entry_indices
+                    .iter()
+                    .map(|i| ScVal::U32(*i))
+                    .collect(),
+            )?,
+        ),
+        (
+            "source_account",
+            bundle_bytes(inputs.source_account.data.as_slice())?,
+        ),
+        ("ttl_entries", bufs(&inputs.ttl_entries)?),
+        ("version", ScVal::U32(INVOCATION_INPUTS_BUNDLE_VERSION)),
+    ])?;
+    Ok(non_metered_xdr_to_rust_buf(&bundle)?)
+}
 
-    let budget = Budget::try_from_configs(
-        instruction_limit as u64,
-        ledger_info.memory_limit as u64,
-        // These are the only non-metered XDR conversions that we perform. They
-        // have a small constant cost that is independent of the user-provided
-        // data.
-        non_metered_xdr_from_cxx_buf::<ContractCostParams>(&ledger_info.cpu_cost_params)?,
-        non_metered_xdr_from_cxx_buf::<ContractCostParams>(&ledger_info.mem_cost_params)?,
-    )?;
-    let mut diagnostic_events = vec![];
-    let ledger_seq_num = ledger_info.sequence_number;
-    let trace_hook: Option<super::soroban_env_host::TraceHook> =
-        if crate::log::is_tx_tracing_enabled() {
-            Some(make_trace_hook_fn())
-        } else {
-            None
-        };
-    let (res, time_nsecs) = {
-        let _span1 = tracy_span!("e2e_invoke::invoke_function");
-        let start_time = Instant::now();
+// The inverse of `serialize_invocation_inputs`.
+#[cfg(feature = "testutils")]
+pub(crate) fn deserialize_invocation_inputs(
+    buf: &CxxBuf,
+) -> Result<crate::InvocationInputsBundle, Box<dyn Error>> {
+    decode_invocation_inputs(buf.data.as_slice())
+}
+
+#[cfg(feature = "testutils")]
+fn decode_invocation_inputs(data: &[u8]) -> Result<crate::InvocationInputsBundle, Box<dyn Error>> {
+    let bundle = ScVal::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(data),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    let version = bundle_field_u32(&bundle, "version")?;
+    if version != INVOCATION_INPUTS_BUNDLE_VERSION {
+        return Err(CoreHostError::General(format!(
+            "unsupported invocation bundle version {}",
+            version
+        ))
+        .into());
+    }
+    let ledger_info = bundle_field(&bundle, "ledger_info")?;
+    let rent_fee_configuration = bundle_field(&bundle, "rent_fee_configuration")?;
+    Ok(crate::InvocationInputsBundle {
+        instruction_limit: bundle_field_u32(&bundle, "instruction_limit")?,
+        hf: bundle_field_bytes(&bundle, "hf")?.into(),
+        resources: bundle_field_bytes(&bundle, "resources")?.into(),
+        restored_rw_entry_indices: bundle_field_vec(&bundle, "restored_rw_entry_indices")?
+            .iter()
+            .map(|val| match val {
+                ScVal::U32(i) => Ok(*i),
+                _ => Err(CoreHostError::General(
+                    "invocation bundle restored_rw_entry_indices holds non-u32".into(),
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        source_account: bundle_field_bytes(&bundle, "source_account")?.into(),
+        auth_entries: bundle_field_bytes_vec(&bundle, "auth_entries")?,
+        protocol_version: bundle_field_u32(ledger_info, "protocol_version")?,
+        sequence_number: bundle_field_u32(ledger_info, "sequence_number")?,
+        timestamp: bundle_field_u64(ledger_info, "timestamp")?,
+        network_id: bundle_field_bytes(ledger_info, "network_id")?,
+        base_reserve: bundle_field_u32(ledger_info, "base_reserve")?,
+        memory_limit: bundle_field_u32(ledger_info, "memory_limit")?,
+        min_temp_entry_ttl: bundle_field_u32(ledger_info, "min_temp_entry_ttl")?,
+        min_persistent_entry_ttl: bundle_field_u32(ledger_info, "min_persistent_entry_ttl")?,
+        max_entry_ttl: bundle_field_u32(ledger_info, "max_entry_ttl")?,
+        cpu_cost_params: bundle_field_bytes(ledger_info, "cpu_cost_params")?.into(),
+        mem_cost_params: bundle_field_bytes(ledger_info, "mem_cost_params")?.into(),
+        ledger_entries: bundle_field_bytes_vec(&bundle, "ledger_entries")?,
+        ttl_entries: bundle_field_bytes_vec(&bundle, "ttl_entries")?,
+        base_prng_seed: bundle_field_bytes(&bundle, "base_prng_seed")?.into(),
+        rent_fee_configuration: CxxRentFeeConfiguration {
+            fee_per_write_1kb: bundle_field_i64(rent_fee_configuration, "fee_per_write_1kb")?,
+            fee_per_rent_1kb: bundle_field_i64(rent_fee_configuration, "fee_per_rent_1kb")?,
+            fee_per_write_entry: bundle_field_i64(rent_fee_configuration, "fee_per_write_entry")?,
+            persistent_rent_rate_denominator: bundle_field_i64(
+                rent_fee_configuration,
+                "persistent_rent_rate_denominator",
+            )?,
+            temporary_rent_rate_denominator: bundle_field_i64(
+                rent_fee_configuration,
+                "temporary_rent_rate_denominator",
+            )?,
+        },
+    })
+}
+
+// Core builds the restored indices while walking the read-write footprint in
+// order, so they must be strictly increasing. A duplicate would make the host
+// restore the same entry twice. Whether they are in bounds is left to the host,
+// which checks them against the footprint it decodes, metered, anyway:
+// decoding the user-provided resources here would be unmetered work on the
+// apply path.
+fn validate_restored_indices(indices: &[u32]) -> Result<(), Box<dyn Error>> {
+    for pair in indices.windows(2) {
+        if pair[1] <= pair[0] {
+            return Err(CoreHostError::General(format!(
+                "restored entry indices not strictly increasing: {} after {}",
+                pair[1], pair[0]
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+// Returns the discriminant of the cost type that consumed the most cpu
+// instructions (the first one in case of a tie).
+fn get_dominant_cpu_cost_type(budget: &Budget) -> Result<u32, HostError> {
+    let mut dominant = (xdr::ContractCostType::VARIANTS[0], 0u64);
+    for ty in xdr::ContractCostType::VARIANTS {
+        let cpu = budget.get_tracker(ty)?.cpu;
+        if cpu > dominant.1 {
+            dominant = (ty, cpu);
+        }
+    }
+    Ok(dominant.0 as u32)
+}
+
+// Returns the discriminants of the cost types that were charged any cpu or
+// memory, in ascending order.
+fn get_charged_cost_types(budget: &Budget) -> Result<Vec<u32>, HostError> {
+    let mut charged = vec![];
+    for ty in xdr::ContractCostType::VARIANTS {
+        let tracker = budget.get_tracker(ty)?;
+        if tracker.cpu != 0 || tracker.mem != 0 {
+            charged.push(ty as u32);
+        }
+    }
+    Ok(charged)
+}
+
+// Logs a summary of the inputs of an invocation. Inputs that fail to decode are
+// logged as such; the invocation itself reports the actual error.
+fn log_invocation_inputs(
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+) {
+    let hf_type = match host_function_type(hf_buf) {
+        Ok(ty) => format!("{}", ty),
+        Err(_) => "<bad HostFunction>".to_string(),
+    };
+    let footprint = match non_metered_xdr_from_cxx_buf::<SorobanResources>(resources_buf) {
+        Ok(resources) => format!(
+            "{} read-only, {} read-write",
+            resources.footprint.read_only.len(),
+            resources.footprint.read_write.len()
+        ),
+        Err(_) => "<bad SorobanResources>".to_string(),
+    };
+    trace!(
+        target: TX,
+        "invoking host function type {}, instruction limit {}, footprint {}, {} auth entries",
+        hf_type,
+        instruction_limit,
+        footprint,
+        auth_entries.len()
+    );
+}
+
+// Builds a diagnostic event of the shape the invocation path emits when the
+// host function fails: void data, the given topics and, optionally, the id of
+// the contract it is attributed to.
+fn make_failure_diagnostic_event(
+    contract_id: Option<[u8; 32]>,
+    topics: Vec<ScVal>,
+) -> DiagnosticEvent {
+    DiagnosticEvent {
+        in_successful_contract_call: false,
+        event: ContractEvent {
+            ext: ExtensionPoint::V0,
+            // `Hash` in older XDR, a `ContractId` wrapping one in newer XDR.
+            #[allow(clippy::useless_conversion)]
+            contract_id: contract_id.map(|id| Hash(id).into()),
+            type_: ContractEventType::Diagnostic,
+            body: ContractEventBody::V0(ContractEventV0 {
+                topics: topics.try_into().unwrap_or_default(),
+                data: ScVal::Void,
+            }),
+        },
+    }
+}
+
+// Encodes a sample diagnostic event with a single `topic` symbol, built the
+// same way as the event the invocation path emits on failure.
+#[cfg(feature = "testutils")]
+pub(crate) fn make_test_diagnostic_event(
+    topic: &str,
+    contract_id: Option<[u8; 32]>,
+) -> Result<RustBuf, Box<dyn Error>> {
+    let topic = ScSymbol(
+        topic
+            .try_into()
+            .map_err(|_| format!("invalid diagnostic event topic: {:?}", topic))?,
+    );
+    let event = make_failure_diagnostic_event(contract_id, vec![ScVal::Symbol(topic)]);
+    Ok(non_metered_xdr_to_rust_buf(&event)?)
+}
+
+// Classifies an invocation failure under ledger protocol `protocol_version`.
+// Before protocol 22, a contract error with the internal error code is an
+// internal error; from protocol 22 on, contract errors never are.
+fn classify_host_error(err: &HostError, protocol_version: u32) -> HostErrorClass {
+    let error = err.error;
+    let is_contract_error = error.is_type(ScErrorType::Contract);
+    if error.is_code(ScErrorCode::InternalError) && (!is_contract_error || protocol_version < 22) {
+        HostErrorClass::InternalFault
+    } else if is_contract_error {
+        HostErrorClass::ContractFault
+    } else if error.is_type(ScErrorType::Budget) && error.is_code(ScErrorCode::ExceededLimit) {
+        HostErrorClass::BudgetExhausted
+    } else if error.is_code(ScErrorCode::InvalidInput)
+        || error.is_code(ScErrorCode::UnexpectedType)
+        || error.is_code(ScErrorCode::UnexpectedSize)
+    {
+        HostErrorClass::MalformedInput
+    } else {
+        HostErrorClass::ContractFault
+    }
+}
+
+// Checks the invocation inputs the host would only reject with an opaque
+// error and builds the budget to meter the invocation against. Shared by
+// `invoke_host_function` and `invoke_and_collect_events`.
+fn prepare_invocation(
+    budget_cpu_limit: u64,
+    budget_mem_limit: u64,
+    restored_rw_entry_indices: &[u32],
+    ledger_info: &CxxLedgerInfo,
+    base_prng_seed: &CxxBuf,
+) -> Result<Budget, Box<dyn Error>> {
+    validate_restored_indices(restored_rw_entry_indices)?;
+    if base_prng_seed.data.len() != BASE_PRNG_SEED_LEN {
+        return Err(CoreHostError::General(format!(
+            "prng seed wrong size: got {}, expected {}",
+            base_prng_seed.data.len(),
+            BASE_PRNG_SEED_LEN
+        ))
+        .into());
+    }
+    // These are the only non-metered XDR conversions that we perform. They
+    // have a small constant cost that is independent of the user-provided
+    // data. (Classifying the ledger changes afterwards reads the key hashes
+    // off the encoded TTL entries without decoding them, see
+    // `input_ttl_key_hashes`.)
+    let cpu_cost_params =
+        non_metered_xdr_from_cxx_buf::<ContractCostParams>(&ledger_info.cpu_cost_params)?;
+    let mem_cost_params =
+        non_metered_xdr_from_cxx_buf::<ContractCostParams>(&ledger_info.mem_cost_params)?;
+    // Unlike compilation (see `CoreCompilationContext`), an invocation needs
+    // a real cost model: with empty params metering would be meaningless.
+    if cpu_cost_params.0.is_empty() || mem_cost_params.0.is_empty() {
+        return Err(CoreHostError::General("cost params empty".into()).into());
+    }
+    Ok(Budget::try_from_configs(
+        budget_cpu_limit,
+        budget_mem_limit,
+        cpu_cost_params,
+        mem_cost_params,
+    )?)
+}
+
+fn invoke_host_function_or_maybe_panic(
+    enable_diagnostics: bool,
+    instruction_limit: u32,
+    hf_buf: &CxxBuf,
+    resources_buf: &CxxBuf,
+    restored_rw_entry_indices: &[u32],
+    source_account_buf: &CxxBuf,
+    auth_entries: &[CxxBuf],
+    ledger_info: &CxxLedgerInfo,
+    // `ledger_info` already converted for the host, so that callers running
+    // several invocations in the same ledger only convert it once.
+    host_ledger_info: LedgerInfo,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    base_prng_seed: &CxxBuf,
+    rent_fee_configuration: &CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
+    module_cache: &crate::SorobanModuleCache,
+    // When set, diagnostic events are passed here as they're encoded instead
+    // of being returned in the output.
+    diagnostic_sink: Option<&mut dyn FnMut(RustBuf)>,
+) -> Result<InvokeHostFunctionOutput, Box<dyn Error>> {
+    #[cfg(feature = "tracy")]
+    let client = tracy_client::Client::start();
+    let _span0 = tracy_span!("invoke_host_function_or_maybe_panic");
+
+    let protocol_version = ledger_info.protocol_version;
+
+    if crate::log::is_tx_tracing_enabled() {
+        log_invocation_inputs(instruction_limit, hf_buf, resources_buf, auth_entries);
+    }
+
+    // Test builds can opt out of budget enforcement to measure the overhead of
+    // metering: costs are still tracked, but the limits are unbounded.
+    let (budget_cpu_limit, budget_mem_limit) = if cfg!(feature = "testutils") && options.unmetered {
+        (u64::MAX, u64::MAX)
+    } else {
+        (instruction_limit as u64, ledger_info.memory_limit as u64)
+    };
+    let budget = prepare_invocation(
+        budget_cpu_limit,
+        budget_mem_limit,
+        restored_rw_entry_indices,
+        ledger_info,
+        base_prng_seed,
+    )?;
+    let mut diagnostic_events = vec![];
+    let ledger_seq_num = ledger_info.sequence_number;
+    // In test builds, the host's trace and diagnostic output can be captured
+    // into the output instead of going to the log.
+    let capture_host_output = cfg!(feature = "testutils") && options.capture_host_output;
+    let host_output = Rc::new(RefCell::new(String::new()));
+    let trace_hook: Option<super::soroban_env_host::TraceHook> = if capture_host_output {
+        let host_output = host_output.clone();
+        Some(make_trace_hook_fn(move |line| {
+            let mut host_output = host_output.borrow_mut();
+            host_output.push_str(&line);
+            host_output.push('\n');
+        }))
+    } else if crate::log::is_tx_tracing_enabled() {
+        Some(make_trace_hook_fn(|line| trace!(target: TX, "{}", line)))
+    } else {
+        None
+    };
+    let (res, time_nsecs) = {
+        let _span1 = tracy_span!("e2e_invoke::invoke_function");
+        let start_time = Instant::now();
 
         let res = super::invoke_host_function_with_trace_hook_and_module_cache(
             &budget,
@@ -438,7 +1578,7 @@ fn invoke_host_function_or_maybe_panic(
             restored_rw_entry_indices,
             source_account_buf,
             auth_entries.iter(),
-            ledger_info.try_into()?,
+            host_ledger_info,
             ledger_entries.iter(),
             ttl_entries.iter(),
             base_prng_seed,
@@ -453,7 +1593,15 @@ fn invoke_host_function_or_maybe_panic(
 
     // Unconditionally log diagnostic events (there won't be any if diagnostics
     // is disabled).
-    log_diagnostic_events(&diagnostic_events);
+    if capture_host_output {
+        let mut host_output = host_output.borrow_mut();
+        for e in &diagnostic_events {
+            host_output.push_str(&format!("Diagnostic event: {:?}\n", e));
+        }
+    } else {
+        log_diagnostic_events(&diagnostic_events);
+    }
+    let host_output = host_output.take();
 
     let cpu_insns = budget.get_cpu_insns_consumed()?;
     let mem_bytes = budget.get_mem_bytes_consumed()?;
@@ -464,6 +1612,8 @@ fn invoke_host_function_or_maybe_panic(
     );
     let time_nsecs_excluding_vm_instantiation =
         time_nsecs.saturating_sub(budget.get_time(xdr::ContractCostType::VmInstantiation)?);
+    let dominant_cpu_cost_type = get_dominant_cpu_cost_type(&budget)?;
+    let charged_cost_types = get_charged_cost_types(&budget)?;
     #[cfg(feature = "tracy")]
     {
         client.plot(
@@ -475,34 +1625,140 @@ fn invoke_host_function_or_maybe_panic(
             mem_bytes as f64,
         );
     }
+    let mut output_too_large = false;
     let err = match res {
         Ok(res) => match res.encoded_invoke_result {
-            Ok(result_value) => {
+            Ok(result_value) => 'success: {
+                // Counted on the host's events, before any of the output is
+                // built from them.
+                let num_contract_events = res.encoded_contract_events.len();
+                if options.max_contract_events != 0
+                    && num_contract_events > options.max_contract_events as usize
+                {
+                    debug!(target: TX, "invocation emitted {} contract events, more than the limit of {}",
+                        num_contract_events, options.max_contract_events);
+                    break 'success (ScErrorType::Events, ScErrorCode::ExceededLimit).into();
+                }
                 let rent_changes = extract_rent_changes(&res.ledger_changes);
                 let rent_fee = host_compute_rent_fee(
                     &rent_changes,
                     &rent_fee_configuration.into(),
                     ledger_seq_num,
                 );
-                let modified_ledger_entries = extract_ledger_effects(res.ledger_changes)?;
+                // The output is checked against `max_output_bytes` as it's
+                // built, so that an oversized output is abandoned before the
+                // rest of it is allocated.
+                let mut output_size = OutputSizeLimit::new(options.max_output_bytes);
+                output_size.add(result_value.len());
+                for event in &res.encoded_contract_events {
+                    output_size.add(event.len());
+                }
+                if output_size.exceeded() {
+                    output_too_large = true;
+                    break 'success output_size.error();
+                }
+                // Any written entry that is byte-identical to its original
+                // value in `ledger_entries` was not actually changed, unless
+                // it was restored: `ledger_entries` also holds the archived
+                // values of restored entries.
+                let unchanged_values: HashSet<&[u8]> = if options.skip_unchanged_writes {
+                    ledger_entries.iter().map(|e| e.data.as_slice()).collect()
+                } else {
+                    HashSet::new()
+                };
+                let touched_keys = if options.include_touched_keys {
+                    extract_touched_keys(&res.ledger_changes)
+                } else {
+                    vec![]
+                };
+                // Built on the first change the host reports without an old
+                // entry, which is rare for anything but newly created entries.
+                let mut input_key_hashes: Option<HashSet<&[u8]>> = None;
+                let any_restored = !restored_rw_entry_indices.is_empty();
+                let ledger_effects =
+                    extract_ledger_effects(res.ledger_changes, &unchanged_values, |change| {
+                        passed_in_live(change, any_restored, |key_hash| {
+                            input_key_hashes
+                                .get_or_insert_with(|| {
+                                    input_ttl_key_hashes(
+                                        ttl_entries.iter().map(|buf| buf.data.as_slice()),
+                                    )
+                                })
+                                .contains(key_hash)
+                        })
+                    })?;
+                for buf in ledger_effects
+                    .modified_entries
+                    .iter()
+                    .chain(ledger_effects.deleted_keys.iter())
+                {
+                    output_size.add(buf.data.len());
+                }
+                if output_size.exceeded() {
+                    output_too_large = true;
+                    break 'success output_size.error();
+                }
+                let result_summary = if options.include_result_summary {
+                    summarize_encoded_scval(&result_value)
+                } else {
+                    String::new()
+                };
+                let contract_events: Vec<RustBuf> = res
+                    .encoded_contract_events
+                    .into_iter()
+                    .map(RustBuf::from)
+                    .collect();
+                if options.max_event_topics != 0 {
+                    if let Err(e) =
+                        validate_event_topic_limits(&contract_events, options.max_event_topics)
+                    {
+                        break 'success e;
+                    }
+                }
+                // Streamed diagnostic events are not part of the output, so
+                // they're only encoded once the invocation has succeeded.
+                let encoded_diagnostic_events = if diagnostic_sink.is_some() {
+                    vec![]
+                } else {
+                    encode_diagnostic_events_within(&diagnostic_events, &mut output_size)
+                };
+                if output_size.exceeded() {
+                    output_too_large = true;
+                    break 'success output_size.error();
+                }
+                if let Some(sink) = diagnostic_sink {
+                    encode_diagnostic_events_with(&diagnostic_events, sink);
+                }
                 return Ok(InvokeHostFunctionOutput {
                     success: true,
                     is_internal_error: false,
-                    diagnostic_events: encode_diagnostic_events(&diagnostic_events),
+                    error_class: HostErrorClass::None,
+                    output_too_large: false,
+                    diagnostic_events: encoded_diagnostic_events,
                     cpu_insns,
                     mem_bytes,
+                    attempted_cpu_insns: cpu_insns,
+                    instruction_limit: budget_cpu_limit,
+                    memory_limit: budget_mem_limit,
                     time_nsecs,
                     cpu_insns_excluding_vm_instantiation,
                     time_nsecs_excluding_vm_instantiation,
+                    dominant_cpu_cost_type,
+                    charged_cost_types,
+                    protocol_version,
 
                     result_value: result_value.into(),
-                    modified_ledger_entries,
-                    contract_events: res
-                        .encoded_contract_events
-                        .into_iter()
-                        .map(RustBuf::from)
-                        .collect(),
+                    modified_ledger_entries: ledger_effects.modified_entries,
+                    modified_ledger_entry_kinds: ledger_effects.modified_entry_kinds,
+                    deleted_ledger_keys: ledger_effects.deleted_keys,
+                    read_only_execution: ledger_effects.read_only,
+                    touched_keys,
+                    contract_events,
                     rent_fee,
+                    result_summary,
+                    host_output,
+                    packed_contract_events: vec![].into(),
+                    packed_diagnostic_events: vec![].into(),
                 });
             }
             Err(e) => e,
@@ -510,62 +1766,99 @@ fn invoke_host_function_or_maybe_panic(
         Err(e) => e,
     };
     if enable_diagnostics {
-        diagnostic_events.push(DiagnosticEvent {
-            in_successful_contract_call: false,
-            event: ContractEvent {
-                ext: ExtensionPoint::V0,
-                contract_id: None,
-                type_: ContractEventType::Diagnostic,
-                body: ContractEventBody::V0(ContractEventV0 {
-                    topics: vec![
-                        ScVal::Symbol(ScSymbol("host_fn_failed".try_into().unwrap_or_default())),
-                        ScVal::Error(
-                            err.error
-                                .try_into()
-                                .unwrap_or(ScError::Context(ScErrorCode::InternalError)),
-                        ),
-                    ]
-                    .try_into()
-                    .unwrap_or_default(),
-                    data: ScVal::Void,
-                }),
-            },
-        })
+        diagnostic_events.push(make_failure_diagnostic_event(
+            None,
+            vec![
+                ScVal::Symbol(ScSymbol("host_fn_failed".try_into().unwrap_or_default())),
+                ScVal::Error(
+                    err.error
+                        .try_into()
+                        .unwrap_or(ScError::Context(ScErrorCode::InternalError)),
+                ),
+            ],
+        ))
     }
-    let is_internal_error = if protocol_version < 22 {
-        err.error.is_code(ScErrorCode::InternalError)
+    let error_class = classify_host_error(&err, protocol_version);
+    let is_internal_error = error_class == HostErrorClass::InternalFault;
+
+    let result_value = if options.include_contract_error_result
+        && protocol_version >= 22
+        && !is_internal_error
+        && err.error.is_type(ScErrorType::Contract)
+    {
+        match ScError::try_from(err.error) {
+            Ok(sc_error) => non_metered_xdr_to_vec(&ScVal::Error(sc_error))?,
+            Err(_) => vec![],
+        }
     } else {
-        err.error.is_code(ScErrorCode::InternalError) && !err.error.is_type(ScErrorType::Contract)
+        vec![]
     };
 
-    debug!(target: TX, "invocation failed: {}", err);
+    debug!(target: TX, "invocation failed ({}): {}", error_class.repr, err);
     return Ok(InvokeHostFunctionOutput {
         success: false,
         is_internal_error,
-        diagnostic_events: encode_diagnostic_events(&diagnostic_events),
+        error_class,
+        output_too_large,
+        diagnostic_events: match diagnostic_sink {
+            Some(sink) => {
+                encode_diagnostic_events_with(&diagnostic_events, sink);
+                vec![]
+            }
+            None => encode_diagnostic_events(&diagnostic_events),
+        },
         cpu_insns,
         mem_bytes,
+        attempted_cpu_insns: cpu_insns,
+        instruction_limit: budget_cpu_limit,
+        memory_limit: budget_mem_limit,
         time_nsecs,
         cpu_insns_excluding_vm_instantiation,
         time_nsecs_excluding_vm_instantiation,
+        dominant_cpu_cost_type,
+        charged_cost_types,
+        protocol_version,
 
-        result_value: vec![].into(),
+        result_value: result_value.into(),
         modified_ledger_entries: vec![],
+        modified_ledger_entry_kinds: vec![],
+        deleted_ledger_keys: vec![],
+        read_only_execution: false,
+        touched_keys: vec![],
         contract_events: vec![],
         rent_fee: 0,
+        result_summary: String::new(),
+        host_output,
+        packed_contract_events: vec![].into(),
+        packed_diagnostic_events: vec![].into(),
     });
 }
 
-#[allow(dead_code)]
-#[cfg(feature = "testutils")]
-pub(crate) fn rustbuf_containing_scval_to_string(buf: &RustBuf) -> String {
-    if let Ok(val) = ScVal::read_xdr(&mut xdr::Limited::new(
-        Cursor::new(buf.data.as_slice()),
+fn decode_scval(data: &[u8]) -> Result<ScVal, xdr::Error> {
+    ScVal::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(data),
         Limits {
             depth: MARSHALLING_STACK_LIMIT,
-            len: buf.data.len(),
+            len: data.len(),
         },
-    )) {
+    ))
+}
+
+// Describes an encoded ScVal by its type name, plus the element count for
+// vectors and maps, e.g. "Vec(3)" or "U32".
+fn summarize_encoded_scval(data: &[u8]) -> String {
+    match decode_scval(data) {
+        Ok(ScVal::Vec(Some(v))) => format!("Vec({})", v.len()),
+        Ok(ScVal::Map(Some(m))) => format!("Map({})", m.len()),
+        Ok(val) => val.name().to_string(),
+        Err(_) => "<bad ScVal>".to_string(),
+    }
+}
+
+#[allow(dead_code)]
+#[cfg(feature = "testutils")]
+pub(crate) fn rustbuf_containing_scval_to_string(buf: &RustBuf) -> String {
+    if let Ok(val) = decode_scval(buf.data.as_slice()) {
         format!("{:?}", val)
     } else {
         "<bad ScVal>".to_string()
@@ -588,6 +1881,46 @@ pub(crate) fn rustbuf_containing_diagnostic_event_to_string(buf: &RustBuf) -> St
     }
 }
 
+// Sentinel group key for diagnostic events that carry no contract_id.
+pub(crate) const NO_CONTRACT_ID: [u8; 32] = [0; 32];
+
+pub(crate) type DiagnosticEventGroups = Vec<([u8; 32], Vec<RustBuf>)>;
+
+// Buckets encoded diagnostic events by the id of the contract that emitted
+// them, with events lacking a contract_id under `NO_CONTRACT_ID`. Groups are
+// in the order their contract first appears, and events keep their relative
+// order within each group.
+pub(crate) fn group_diagnostic_events_by_contract(
+    events: &[RustBuf],
+) -> Result<DiagnosticEventGroups, Box<dyn Error>> {
+    let mut groups: DiagnosticEventGroups = vec![];
+    for buf in events.iter() {
+        let event = DiagnosticEvent::read_xdr(&mut xdr::Limited::new(
+            Cursor::new(buf.data.as_slice()),
+            Limits {
+                depth: MARSHALLING_STACK_LIMIT,
+                len: buf.data.len(),
+            },
+        ))
+        .map_err(CoreHostError::from)?;
+        // The contract_id is a `Hash` in older XDR and a `ContractId` wrapping
+        // one in newer XDR; `Hash::from` accepts both.
+        #[allow(clippy::useless_conversion)]
+        let key = match event.event.contract_id {
+            Some(id) => Hash::from(id).0,
+            None => NO_CONTRACT_ID,
+        };
+        let copy = RustBuf {
+            data: buf.data.clone(),
+        };
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(copy),
+            None => groups.push((key, vec![copy])),
+        }
+    }
+    Ok(groups)
+}
+
 pub(crate) fn compute_transaction_resource_fee(
     tx_resources: CxxTransactionResources,
     fee_config: CxxFeeConfiguration,
@@ -600,8 +1933,51 @@ pub(crate) fn compute_transaction_resource_fee(
     }
 }
 
+// Fixed-point denominator of `compute_transaction_resource_fee_exact`. Fees
+// are charged per 10000 instructions or per 1024 bytes, both of which divide
+// this scale.
+#[cfg(feature = "testutils")]
+pub(crate) const EXACT_FEE_SCALE: i64 = 640_000;
+
+// Like `compute_transaction_resource_fee`, but returns the fees before they are
+// rounded to whole stroops, as fixed-point values with denominator
+// `EXACT_FEE_SCALE`. This runs the host's own computation with every fee rate
+// multiplied by the scale, so that each per-increment division is exact.
+#[allow(dead_code)]
+#[cfg(feature = "testutils")]
+pub(crate) fn compute_transaction_resource_fee_exact(
+    tx_resources: CxxTransactionResources,
+    fee_config: CxxFeeConfiguration,
+) -> FeePair {
+    let scaled_fee_config = CxxFeeConfiguration {
+        fee_per_instruction_increment: fee_config
+            .fee_per_instruction_increment
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_disk_read_entry: fee_config
+            .fee_per_disk_read_entry
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_write_entry: fee_config
+            .fee_per_write_entry
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_disk_read_1kb: fee_config
+            .fee_per_disk_read_1kb
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_write_1kb: fee_config.fee_per_write_1kb.saturating_mul(EXACT_FEE_SCALE),
+        fee_per_historical_1kb: fee_config
+            .fee_per_historical_1kb
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_contract_event_1kb: fee_config
+            .fee_per_contract_event_1kb
+            .saturating_mul(EXACT_FEE_SCALE),
+        fee_per_transaction_size_1kb: fee_config
+            .fee_per_transaction_size_1kb
+            .saturating_mul(EXACT_FEE_SCALE),
+    };
+    compute_transaction_resource_fee(tx_resources, scaled_fee_config)
+}
+
 pub(crate) fn compute_rent_fee(
-    changed_entries: &Vec<CxxLedgerEntryRentChange>,
+    changed_entries: &[CxxLedgerEntryRentChange],
     fee_config: CxxRentFeeConfiguration,
     current_ledger_seq: u32,
 ) -> i64 {
@@ -613,6 +1989,276 @@ pub(crate) fn compute_rent_fee(
     )
 }
 
+pub(crate) fn compute_rent_fee_itemized(
+    changed_entries: &[CxxLedgerEntryRentChange],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+) -> Vec<i64> {
+    let fee_config: RentFeeConfiguration = (&fee_config).into();
+    changed_entries
+        .iter()
+        .map(|e| host_compute_rent_fee(&[e.into()], &fee_config, current_ledger_seq))
+        .collect()
+}
+
+// Rent fee of extending the TTL of every contract entry of the read-write
+// footprint of `resources_buf` to the maximum, i.e. to live until ledger
+// `current_ledger_seq + max_ttl - 1`. `ledger_entries` and `ttl_entries` are
+// paired like the inputs of `invoke_host_function`; entries outside the
+// read-write footprint are ignored. Entries are sized for rent as in
+// `ledger_entry_rent_bytes`, which needs the network cost parameters.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn estimate_max_ttl_bump_fee(
+    resources_buf: &CxxBuf,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+    fee_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_ttl: u32,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<i64, Box<dyn Error>> {
+    let resources = SorobanResources::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(resources_buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: resources_buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    if ledger_entries.len() != ttl_entries.len() {
+        return Err(CoreHostError::General(format!(
+            "{} ttl entries provided for {} ledger entries",
+            ttl_entries.len(),
+            ledger_entries.len()
+        ))
+        .into());
+    }
+    let max_live_until_ledger = current_ledger_seq.saturating_add(max_ttl).saturating_sub(1);
+    let mut rent_changes: Vec<CxxLedgerEntryRentChange> = vec![];
+    for (entry_buf, ttl_buf) in ledger_entries.iter().zip(ttl_entries.iter()) {
+        let entry = non_metered_xdr_from_cxx_buf::<LedgerEntry>(entry_buf)?;
+        let Some(key) = contract_entry_key(&entry) else {
+            continue;
+        };
+        if !resources.footprint.read_write.contains(&key) {
+            continue;
+        }
+        let old_live_until_ledger = if ttl_buf.data.is_empty() {
+            0
+        } else {
+            non_metered_xdr_from_cxx_buf::<TtlEntry>(ttl_buf)?.live_until_ledger_seq
+        };
+        let size = entry_rent_bytes(
+            &entry,
+            entry_buf.data.len(),
+            cpu_cost_params,
+            mem_cost_params,
+        )?;
+        rent_changes.push(CxxLedgerEntryRentChange {
+            is_persistent: match &key {
+                LedgerKey::ContractData(data) => {
+                    data.durability == xdr::ContractDataDurability::Persistent
+                }
+                _ => true,
+            },
+            is_code_entry: matches!(key, LedgerKey::ContractCode(_)),
+            old_size_bytes: size,
+            new_size_bytes: size,
+            old_live_until_ledger,
+            new_live_until_ledger: old_live_until_ledger.max(max_live_until_ledger),
+        });
+    }
+    Ok(compute_rent_fee(
+        &rent_changes,
+        fee_config,
+        current_ledger_seq,
+    ))
+}
+
+// Fee of a RestoreFootprint operation restoring the encoded `entries`,
+// computed the way the operation does: the write fee for writing the entries
+// back, plus the rent fee for each entry living, at its rent size, until ledger
+// `current_ledger_seq + min_persistent_ttl - 1`, with the rent rate derived
+// from `bucket_list_size`. Only persistent contract entries can be restored.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compute_restore_footprint_fee(
+    entries: &[CxxBuf],
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+    bucket_list_size: i64,
+    write_config: CxxRentWriteFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    min_persistent_ttl: u32,
+) -> Result<i64, Box<dyn Error>> {
+    let rent_config = restore_rent_fee_config(
+        get_max_proto(),
+        compute_rent_write_fee_per_1kb(bucket_list_size, write_config),
+        rent_config,
+    );
+    let restored_live_until_ledger = current_ledger_seq
+        .saturating_add(min_persistent_ttl)
+        .saturating_sub(1);
+    let mut rent_changes: Vec<CxxLedgerEntryRentChange> = Vec::with_capacity(entries.len());
+    let mut write_bytes: u32 = 0;
+    for entry_buf in entries {
+        let entry = non_metered_xdr_from_cxx_buf::<LedgerEntry>(entry_buf)?;
+        let is_code_entry = match &entry.data {
+            LedgerEntryData::ContractCode(_) => true,
+            LedgerEntryData::ContractData(data)
+                if data.durability == xdr::ContractDataDurability::Persistent =>
+            {
+                false
+            }
+            _ => {
+                return Err(CoreHostError::General(format!(
+                    "entry is not a persistent contract entry: {:?}",
+                    contract_entry_key(&entry)
+                ))
+                .into())
+            }
+        };
+        let size = ledger_entry_rent_bytes(entry_buf, cpu_cost_params, mem_cost_params)?;
+        write_bytes = write_bytes.saturating_add(entry_buf.data.len() as u32);
+        rent_changes.push(CxxLedgerEntryRentChange {
+            is_persistent: true,
+            is_code_entry,
+            old_size_bytes: 0,
+            new_size_bytes: size,
+            old_live_until_ledger: 0,
+            new_live_until_ledger: restored_live_until_ledger,
+        });
+    }
+    Ok(restore_fee_for_rent_changes(
+        rent_changes,
+        write_bytes,
+        rent_config,
+        current_ledger_seq,
+    ))
+}
+
+// Sets the rent rate of `rent_config` to the one derived from the bucket list
+// size. Before protocol 23 that rate also applies to ledger writes, like in
+// `compute_full_soroban_fee`: the flat write rate in `fee_per_write_1kb` only
+// exists from protocol 23 on, and core passes 0 for it before.
+fn restore_rent_fee_config(
+    max_proto: u32,
+    rent_write_fee_per_1kb: i64,
+    mut rent_config: CxxRentFeeConfiguration,
+) -> CxxRentFeeConfiguration {
+    rent_config.fee_per_rent_1kb = rent_write_fee_per_1kb;
+    if max_proto < 23 {
+        rent_config.fee_per_write_1kb = rent_write_fee_per_1kb;
+    }
+    rent_config
+}
+
+// Write fee of the restored entries, charged like
+// `compute_transaction_resource_fee` charges `write_bytes` over
+// `rent_changes.len()` written entries, plus their rent fee.
+fn restore_fee_for_rent_changes(
+    rent_changes: Vec<CxxLedgerEntryRentChange>,
+    write_bytes: u32,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+) -> i64 {
+    let write_fee = compute_transaction_resource_fee(
+        CxxTransactionResources {
+            instructions: 0,
+            disk_read_entries: 0,
+            write_entries: rent_changes.len() as u32,
+            disk_read_bytes: 0,
+            write_bytes,
+            contract_events_size_bytes: 0,
+            transaction_size_bytes: 0,
+        },
+        CxxFeeConfiguration {
+            fee_per_instruction_increment: 0,
+            fee_per_disk_read_entry: 0,
+            fee_per_write_entry: rent_config.fee_per_write_entry,
+            fee_per_disk_read_1kb: 0,
+            fee_per_write_1kb: rent_config.fee_per_write_1kb,
+            fee_per_historical_1kb: 0,
+            fee_per_contract_event_1kb: 0,
+            fee_per_transaction_size_1kb: 0,
+        },
+    );
+    let rent_fee = compute_rent_fee(&rent_changes, rent_config, current_ledger_seq);
+    write_fee
+        .non_refundable_fee
+        .saturating_add(write_fee.refundable_fee)
+        .saturating_add(rent_fee)
+}
+
+// Upper bound of the fee of a transaction with the given resources: every
+// footprint entry is assumed to be read from disk, and every read-write entry
+// with a TTL to be newly written with `write_bytes` bytes and live for
+// `max_entry_ttl` ledgers. The resources don't tell the size of the events or
+// of the transaction itself, so the fees for those are not included.
+pub(crate) fn estimate_worst_case_fee(
+    resources_buf: &CxxBuf,
+    fee_config: CxxFeeConfiguration,
+    rent_config: CxxRentFeeConfiguration,
+    current_ledger_seq: u32,
+    max_entry_ttl: u32,
+) -> Result<FeePair, Box<dyn Error>> {
+    let resources = SorobanResources::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(resources_buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: resources_buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    let footprint = &resources.footprint;
+    let read_write_entries = footprint.read_write.len() as u32;
+    let resource_fee = compute_transaction_resource_fee(
+        CxxTransactionResources {
+            instructions: resources.instructions,
+            disk_read_entries: (footprint.read_only.len() as u32)
+                .saturating_add(read_write_entries),
+            write_entries: read_write_entries,
+            disk_read_bytes: super::soroban_resources_read_bytes(&resources),
+            write_bytes: resources.write_bytes,
+            contract_events_size_bytes: 0,
+            transaction_size_bytes: 0,
+        },
+        fee_config,
+    );
+    let max_live_until_ledger = current_ledger_seq
+        .saturating_add(max_entry_ttl)
+        .saturating_sub(1);
+    let rent_changes: Vec<CxxLedgerEntryRentChange> = footprint
+        .read_write
+        .iter()
+        .filter_map(|key| {
+            let (is_persistent, is_code_entry) = match key {
+                LedgerKey::ContractData(data) => (
+                    data.durability == xdr::ContractDataDurability::Persistent,
+                    false,
+                ),
+                LedgerKey::ContractCode(_) => (true, true),
+                // Other entries don't pay rent.
+                _ => return None,
+            };
+            Some(CxxLedgerEntryRentChange {
+                is_persistent,
+                is_code_entry,
+                old_size_bytes: 0,
+                new_size_bytes: resources.write_bytes,
+                old_live_until_ledger: 0,
+                new_live_until_ledger: max_live_until_ledger,
+            })
+        })
+        .collect();
+    let rent_fee = compute_rent_fee(&rent_changes, rent_config, current_ledger_seq);
+    Ok(FeePair {
+        non_refundable_fee: resource_fee.non_refundable_fee,
+        refundable_fee: resource_fee.refundable_fee.saturating_add(rent_fee),
+    })
+}
+
 pub(crate) fn compute_rent_write_fee_per_1kb(
     bucket_list_size: i64,
     fee_config: CxxRentWriteFeeConfiguration,
@@ -627,15 +2273,146 @@ pub(crate) fn contract_code_memory_size_for_rent(
 ) -> Result<u32, Box<dyn std::error::Error>> {
     let contract_code_entry =
         non_metered_xdr_from_cxx_buf::<ContractCodeEntry>(contract_code_entry_xdr)?;
+    contract_code_memory_size(&contract_code_entry, cpu_cost_params, mem_cost_params)?
+        .try_into()
+        .map_err(Into::into)
+}
+
+fn contract_code_memory_size(
+    contract_code_entry: &ContractCodeEntry,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<u64, Box<dyn std::error::Error>> {
     let budget = Budget::try_from_configs(
         0,
         0,
         non_metered_xdr_from_cxx_buf::<ContractCostParams>(cpu_cost_params)?,
         non_metered_xdr_from_cxx_buf::<ContractCostParams>(mem_cost_params)?,
     )?;
-    super::wasm_module_memory_cost_wrapper(&budget, &contract_code_entry)?
-        .try_into()
-        .map_err(Into::into)
+    Ok(super::wasm_module_memory_cost_wrapper(
+        &budget,
+        contract_code_entry,
+    )?)
+}
+
+// The size of an encoded `LedgerEntry` that rent is charged for, as
+// `ledgerEntrySizeForRent` computes it on the C++ side: the encoded size,
+// plus the in-memory size of the module for contract code from protocol 23
+// (saturating at `u32::MAX`).
+pub(crate) fn ledger_entry_rent_bytes(
+    entry_buf: &CxxBuf,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let entry: LedgerEntry = non_metered_xdr_from_cxx_buf(entry_buf)?;
+    entry_rent_bytes(
+        &entry,
+        entry_buf.data.len(),
+        cpu_cost_params,
+        mem_cost_params,
+    )
+}
+
+// `ledger_entry_rent_bytes` of an already decoded entry with an encoding of
+// `encoded_len` bytes.
+fn entry_rent_bytes(
+    entry: &LedgerEntry,
+    encoded_len: usize,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let memory_size = match &entry.data {
+        // Hosts before protocol 23 don't count module memory towards rent.
+        LedgerEntryData::ContractCode(code) if get_max_proto() >= 23 => {
+            contract_code_memory_size(code, cpu_cost_params, mem_cost_params)?
+        }
+        _ => 0,
+    };
+    let size = (encoded_len as u64).saturating_add(memory_size);
+    Ok(size.min(u32::MAX as u64) as u32)
+}
+
+// Charges the cost of parsing and instantiating the contract code to a fresh
+// budget and returns the (cpu, mem) it consumed. This is the cost that the
+// module cache saves on every invocation that hits it.
+pub(crate) fn vm_instantiation_cost(
+    contract_code_entry_xdr: &CxxBuf,
+    cpu_cost_params: &CxxBuf,
+    mem_cost_params: &CxxBuf,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let contract_code_entry =
+        non_metered_xdr_from_cxx_buf::<ContractCodeEntry>(contract_code_entry_xdr)?;
+    let budget = Budget::try_from_configs(
+        u64::MAX,
+        u64::MAX,
+        non_metered_xdr_from_cxx_buf::<ContractCostParams>(cpu_cost_params)?,
+        non_metered_xdr_from_cxx_buf::<ContractCostParams>(mem_cost_params)?,
+    )?;
+    charge_vm_instantiation(&budget, &contract_code_entry)?;
+    Ok((
+        budget.get_cpu_insns_consumed()?,
+        budget.get_mem_bytes_consumed()?,
+    ))
+}
+
+// Charges `budget` the way the host does when it parses and instantiates
+// `contract_code_entry`: per cost input when the entry carries refined cost
+// inputs, and `VmInstantiation` on the code size otherwise.
+fn charge_vm_instantiation(
+    budget: &Budget,
+    contract_code_entry: &ContractCodeEntry,
+) -> Result<(), HostError> {
+    use xdr::ContractCostType as Ty;
+    let inputs = match &contract_code_entry.ext {
+        xdr::ContractCodeEntryExt::V0 => {
+            return budget.charge(
+                Ty::VmInstantiation,
+                Some(contract_code_entry.code.len() as u64),
+            );
+        }
+        xdr::ContractCodeEntryExt::V1(v1) => &v1.cost_inputs,
+    };
+    let charges = [
+        (Ty::ParseWasmInstructions, Some(inputs.n_instructions)),
+        (Ty::ParseWasmFunctions, Some(inputs.n_functions)),
+        (Ty::ParseWasmGlobals, Some(inputs.n_globals)),
+        (Ty::ParseWasmTableEntries, Some(inputs.n_table_entries)),
+        (Ty::ParseWasmTypes, Some(inputs.n_types)),
+        (Ty::ParseWasmDataSegments, Some(inputs.n_data_segments)),
+        (Ty::ParseWasmElemSegments, Some(inputs.n_elem_segments)),
+        (Ty::ParseWasmImports, Some(inputs.n_imports)),
+        (Ty::ParseWasmExports, Some(inputs.n_exports)),
+        (
+            Ty::ParseWasmDataSegmentBytes,
+            Some(inputs.n_data_segment_bytes),
+        ),
+        (Ty::InstantiateWasmInstructions, None),
+        (Ty::InstantiateWasmFunctions, Some(inputs.n_functions)),
+        (Ty::InstantiateWasmGlobals, Some(inputs.n_globals)),
+        (
+            Ty::InstantiateWasmTableEntries,
+            Some(inputs.n_table_entries),
+        ),
+        (Ty::InstantiateWasmTypes, None),
+        (
+            Ty::InstantiateWasmDataSegments,
+            Some(inputs.n_data_segments),
+        ),
+        (
+            Ty::InstantiateWasmElemSegments,
+            Some(inputs.n_elem_segments),
+        ),
+        (Ty::InstantiateWasmImports, Some(inputs.n_imports)),
+        (Ty::InstantiateWasmExports, Some(inputs.n_exports)),
+        (
+            Ty::InstantiateWasmDataSegmentBytes,
+            Some(inputs.n_data_segment_bytes),
+        ),
+    ];
+    for (ty, input) in charges {
+        budget.charge(ty, input.map(u64::from))?;
+    }
+    Ok(())
 }
 
 pub(crate) fn can_parse_transaction(xdr: &CxxBuf, depth_limit: u32) -> bool {
@@ -649,6 +2426,290 @@ pub(crate) fn can_parse_transaction(xdr: &CxxBuf, depth_limit: u32) -> bool {
     res.is_ok()
 }
 
+// Returns whether a `TransactionEnvelope` contains any Soroban operation
+// (`InvokeHostFunction`, `ExtendFootprintTtl` or `RestoreFootprint`). Fails if
+// the envelope can't be parsed.
+pub(crate) fn is_soroban_transaction(
+    xdr: &CxxBuf,
+    depth_limit: u32,
+) -> Result<bool, Box<dyn Error>> {
+    let envelope = TransactionEnvelope::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(xdr.data.as_slice()),
+        Limits {
+            depth: depth_limit,
+            len: xdr.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    let operations = match &envelope {
+        TransactionEnvelope::TxV0(env) => &env.tx.operations,
+        TransactionEnvelope::Tx(env) => &env.tx.operations,
+        TransactionEnvelope::TxFeeBump(env) => match &env.tx.inner_tx {
+            xdr::FeeBumpTransactionInnerTx::Tx(inner) => &inner.tx.operations,
+        },
+    };
+    Ok(operations.iter().any(|op| {
+        matches!(
+            op.body,
+            xdr::OperationBody::InvokeHostFunction(_)
+                | xdr::OperationBody::ExtendFootprintTtl(_)
+                | xdr::OperationBody::RestoreFootprint(_)
+        )
+    }))
+}
+
+pub(crate) fn host_function_encoded_size(hf_buf: &CxxBuf) -> Result<usize, Box<dyn Error>> {
+    // Unlike `non_metered_xdr_from_cxx_buf` this is decoding untrusted input,
+    // so a failure is reported as invalid input rather than an internal error.
+    xdr::HostFunction::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(hf_buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: hf_buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    Ok(hf_buf.data.len())
+}
+
+// Decodes only the leading `HostFunctionType` discriminant of an encoded
+// `HostFunction`, without decoding (or validating) its body.
+pub(crate) fn host_function_type(hf_buf: &CxxBuf) -> Result<u32, Box<dyn Error>> {
+    let ty = xdr::HostFunctionType::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(hf_buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: hf_buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    Ok(ty as u32)
+}
+
+// Decodes an encoded invocation source account (an `AccountId`) and returns
+// its strkey ("G...") form.
+pub(crate) fn decode_source_account(buf: &CxxBuf) -> Result<String, Box<dyn Error>> {
+    let account_id = xdr::AccountId::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    Ok(account_id.to_string())
+}
+
+// The inverse of the `TtlEntry` construction in `extract_ledger_effects`:
+// returns the live-until ledger and key hash of an encoded `TtlEntry`.
+pub(crate) fn decode_ttl_entry(buf: &CxxBuf) -> Result<(u32, [u8; 32]), Box<dyn Error>> {
+    let ttl_entry = TtlEntry::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    Ok((ttl_entry.live_until_ledger_seq, ttl_entry.key_hash.0))
+}
+
+// Checks that no key appears in both the read-only and the read-write
+// footprint of an encoded `SorobanResources`.
+pub(crate) fn validate_footprint_disjoint(resources_buf: &CxxBuf) -> Result<(), Box<dyn Error>> {
+    let resources = SorobanResources::read_xdr(&mut xdr::Limited::new(
+        Cursor::new(resources_buf.data.as_slice()),
+        Limits {
+            depth: MARSHALLING_STACK_LIMIT,
+            len: resources_buf.data.len(),
+        },
+    ))
+    .map_err(CoreHostError::from)?;
+    let footprint = &resources.footprint;
+    if let Some(key) = footprint
+        .read_write
+        .iter()
+        .find(|key| footprint.read_only.contains(key))
+    {
+        return Err(CoreHostError::General(format!(
+            "key appears in both read-only and read-write footprint: {:?}",
+            key
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns the hashes of all the wasm modules a transaction may need: those of
+/// any ContractCode keys in its footprint, plus those of any contract instances
+/// (stored in ContractData entries) among the provided `ledger_entries`. The
+/// result is deduplicated and in first-seen order.
+pub(crate) fn extract_referenced_code_hashes(
+    resources_buf: &CxxBuf,
+    ledger_entries: &[CxxBuf],
+) -> Result<Vec<[u8; 32]>, Box<dyn Error>> {
+    let resources = non_metered_xdr_from_cxx_buf::<SorobanResources>(resources_buf)?;
+    let mut hashes: Vec<[u8; 32]> = vec![];
+    let mut note_hash = |hash: &Hash| {
+        if !hashes.contains(&hash.0) {
+            hashes.push(hash.0);
+        }
+    };
+    for key in resources
+        .footprint
+        .read_only
+        .iter()
+        .chain(resources.footprint.read_write.iter())
+    {
+        if let LedgerKey::ContractCode(code_key) = key {
+            note_hash(&code_key.hash);
+        }
+    }
+    for buf in ledger_entries {
+        let entry = non_metered_xdr_from_cxx_buf::<LedgerEntry>(buf)?;
+        match &entry.data {
+            LedgerEntryData::ContractCode(code) => note_hash(&code.hash),
+            LedgerEntryData::ContractData(data) => {
+                if let ScVal::ContractInstance(instance) = &data.val {
+                    if let ContractExecutable::Wasm(hash) = &instance.executable {
+                        note_hash(hash);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(hashes)
+}
+
+// Decodes the encoded `SorobanAuthorizationEntry`s, each within `depth_limit`,
+// and returns their count and the depth of the deepest invocation tree among
+// them (a root invocation without sub-invocations has depth 1).
+pub(crate) fn auth_complexity(
+    auth_entries: &[CxxBuf],
+    depth_limit: u32,
+) -> Result<(usize, u32), Box<dyn Error>> {
+    let mut max_depth = 0;
+    for buf in auth_entries {
+        let entry = xdr::SorobanAuthorizationEntry::read_xdr(&mut xdr::Limited::new(
+            Cursor::new(buf.data.as_slice()),
+            Limits {
+                depth: depth_limit,
+                len: buf.data.len(),
+            },
+        ))
+        .map_err(CoreHostError::from)?;
+        let mut pending = vec![(&entry.root_invocation, 1u32)];
+        while let Some((invocation, depth)) = pending.pop() {
+            max_depth = max_depth.max(depth);
+            pending.extend(
+                invocation
+                    .sub_invocations
+                    .iter()
+                    .map(|sub| (sub, depth.saturating_add(1))),
+            );
+        }
+    }
+    Ok((auth_entries.len(), max_depth))
+}
+
+// Checks that the wasm of an encoded `ContractCodeEntry` hashes to the hash
+// the entry is keyed by, and returns that hash.
+pub(crate) fn validate_contract_code_entry(buf: &CxxBuf) -> Result<[u8; 32], Box<dyn Error>> {
+    let entry = non_metered_xdr_from_cxx_buf::<ContractCodeEntry>(buf)?;
+    let wasm_hash = Hash(Sha256::digest(entry.code.as_slice()).into());
+    if wasm_hash != entry.hash {
+        return Err(CoreHostError::General(format!(
+            "contract code entry hash {} does not match its wasm hash {}",
+            entry.hash, wasm_hash
+        ))
+        .into());
+    }
+    Ok(wasm_hash.0)
+}
+
+// Returns the id of the contract an encoded `HostFunction` invokes, or `None`
+// for functions that upload wasm or create a contract.
+pub(crate) fn invoked_contract_address(
+    hf_buf: &CxxBuf,
+) -> Result<Option<[u8; 32]>, Box<dyn Error>> {
+    let hf = non_metered_xdr_from_cxx_buf::<xdr::HostFunction>(hf_buf)?;
+    let xdr::HostFunction::InvokeContract(args) = hf else {
+        return Ok(None);
+    };
+    match args.contract_address {
+        // `Hash` in older XDR, a `ContractId` wrapping one in newer XDR.
+        #[allow(clippy::useless_conversion)]
+        xdr::ScAddress::Contract(id) => Ok(Some(Hash::from(id).0)),
+        address => Err(CoreHostError::General(format!(
+            "invoked address {} is not a contract",
+            address
+        ))
+        .into()),
+    }
+}
+
+// Returns the key of a contract data or code entry, the kinds of entry that
+// have a TTL.
+fn contract_entry_key(entry: &LedgerEntry) -> Option<LedgerKey> {
+    match &entry.data {
+        LedgerEntryData::ContractData(data) => {
+            Some(LedgerKey::ContractData(xdr::LedgerKeyContractData {
+                contract: data.contract.clone(),
+                key: data.key.clone(),
+                durability: data.durability,
+            }))
+        }
+        LedgerEntryData::ContractCode(code) => {
+            Some(LedgerKey::ContractCode(xdr::LedgerKeyContractCode {
+                hash: code.hash.clone(),
+            }))
+        }
+        _ => None,
+    }
+}
+
+// Checks that `ttl_entries` lines up with `ledger_entries` the way the host
+// pairs them: one (possibly empty) TTL entry per ledger entry, in the same
+// order, each one keyed by the hash of the key of its ledger entry. Only
+// contract data and code entries can have a TTL entry.
+pub(crate) fn validate_ttl_entry_correspondence(
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
+) -> Result<(), Box<dyn Error>> {
+    if ledger_entries.len() != ttl_entries.len() {
+        return Err(CoreHostError::General(format!(
+            "{} ttl entries provided for {} ledger entries",
+            ttl_entries.len(),
+            ledger_entries.len()
+        ))
+        .into());
+    }
+    for (i, (entry_buf, ttl_buf)) in ledger_entries.iter().zip(ttl_entries.iter()).enumerate() {
+        if ttl_buf.data.is_empty() {
+            continue;
+        }
+        let entry = non_metered_xdr_from_cxx_buf::<LedgerEntry>(entry_buf)?;
+        let Some(key) = contract_entry_key(&entry) else {
+            return Err(CoreHostError::General(format!(
+                "ledger entry {} has a ttl entry but is not a contract data or code entry",
+                i
+            ))
+            .into());
+        };
+        let key_hash: [u8; 32] = Sha256::digest(non_metered_xdr_to_vec(&key)?).into();
+        let ttl_entry = non_metered_xdr_from_cxx_buf::<TtlEntry>(ttl_buf)?;
+        if ttl_entry.key_hash.0 != key_hash {
+            return Err(CoreHostError::General(format!(
+                "ttl entry {} does not match the key of the ledger entry it is paired with",
+                i
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 struct CoreCompilationContext {
@@ -697,6 +2758,19 @@ impl ErrorHandler for CoreCompilationContext {
     }
 }
 
+// The keys of the modules in a `ProtocolSpecificModuleCache`. The host's
+// `ModuleCache` can't enumerate its contents, so we record the key of each
+// module as `compile` adds it. Modules added to the `ModuleCache` by any other
+// route are not tracked here.
+#[derive(Default)]
+pub(crate) struct ModuleKeys {
+    // The protocol version each module was compiled for, by key.
+    pub(crate) cached: HashMap<[u8; 32], u32>,
+    // Keys that `clear` and `evict` leave in place. A key may be pinned before
+    // its module is compiled.
+    pub(crate) pinned: HashSet<[u8; 32]>,
+}
+
 #[allow(dead_code)]
 pub(crate) struct ProtocolSpecificModuleCache {
     // `ModuleCache` itself is threadsafe -- does its own internal locking -- so
@@ -707,6 +2781,8 @@ pub(crate) struct ProtocolSpecificModuleCache {
     // threads, we make a throwaway `CompilationContext` on each `compile` call,
     // and _copy out_ the memory usage (which we want to publish back to core).
     pub(crate) mem_bytes_consumed: std::sync::atomic::AtomicU64,
+    // Shared between shallow clones, like `module_cache` itself.
+    pub(crate) module_keys: Arc<Mutex<ModuleKeys>>,
 }
 
 #[allow(dead_code)]
@@ -717,10 +2793,23 @@ impl ProtocolSpecificModuleCache {
         Ok(ProtocolSpecificModuleCache {
             module_cache,
             mem_bytes_consumed: std::sync::atomic::AtomicU64::new(0),
+            module_keys: Arc::new(Mutex::new(ModuleKeys::default())),
         })
     }
 
-    pub(crate) fn compile(&mut self, wasm: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    fn lock_module_keys(&self) -> Result<MutexGuard<'_, ModuleKeys>, Box<dyn std::error::Error>> {
+        self.module_keys
+            .lock()
+            .map_err(|_| CoreHostError::General("module keys lock poisoned".into()).into())
+    }
+
+    // Compiles `wasm` and records `ledger_protocol`, the protocol of the
+    // ledger that needs the module, as the protocol it was compiled for.
+    pub(crate) fn compile(
+        &mut self,
+        ledger_protocol: u32,
+        wasm: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let compilation_context = CoreCompilationContext::new()?;
         let res = self.module_cache.parse_and_cache_module_simple(
             &compilation_context,
@@ -733,16 +2822,153 @@ impl ProtocolSpecificModuleCache {
                 .get_mem_bytes_consumed()?,
             std::sync::atomic::Ordering::SeqCst,
         );
-        Ok(res?)
+        res?;
+        let key: [u8; 32] = Sha256::digest(wasm).into();
+        self.lock_module_keys()?.cached.insert(key, ledger_protocol);
+        Ok(())
     }
 
-    pub(crate) fn evict(&mut self, key: &[u8; 32]) -> Result<(), Box<dyn std::error::Error>> {
-        let _ = self.module_cache.remove_module(&key.clone().into())?;
+    // Like `compile`, but also returns the wall-clock time the compilation took,
+    // in nanoseconds. Memory usage is accounted exactly as in `compile`.
+    pub(crate) fn compile_timed(
+        &mut self,
+        ledger_protocol: u32,
+        wasm: &[u8],
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let start_time = Instant::now();
+        self.compile(ledger_protocol, wasm)?;
+        Ok(start_time.elapsed().as_nanos() as u64)
+    }
+
+    // Checks that `wasm` compiles under the current protocol without touching
+    // the shared cache: the module is compiled into a throwaway `ModuleCache`
+    // (with its own engine) that is dropped on return, and no memory usage is
+    // accounted to `self`.
+    pub(crate) fn validate_wasm(&self, wasm: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let compilation_context = CoreCompilationContext::new()?;
+        let scratch_cache = ModuleCache::new(&compilation_context)?;
+        scratch_cache.parse_and_cache_module_simple(&compilation_context, get_max_proto(), wasm)?;
         Ok(())
     }
 
+    // Compiles `wasm` like `validate_wasm` does and returns the names of the
+    // functions it exports, in export order.
+    pub(crate) fn inspect_wasm_exports(
+        &self,
+        wasm: &[u8],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let compilation_context = CoreCompilationContext::new()?;
+        let scratch_cache = ModuleCache::new(&compilation_context)?;
+        scratch_cache.parse_and_cache_module_simple(&compilation_context, get_max_proto(), wasm)?;
+        let key = Hash(Sha256::digest(wasm).into());
+        super::module_function_exports(&scratch_cache, &key)?
+            .ok_or_else(|| "compiled module missing from scratch cache".into())
+    }
+
+    // Cheap pre-compilation check of `wasm` against the contract size limit.
+    // Only the byte length is checked: the post-compilation memory estimate
+    // depends on the network's cost parameters, which the cache doesn't have.
+    pub(crate) fn wasm_within_size_limit(
+        &self,
+        wasm: &[u8],
+        max_contract_size_bytes: u32,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(wasm.len() <= max_contract_size_bytes as usize)
+    }
+
+    // Cheap upfront estimate of the memory compiling `wasm` would consume,
+    // under the given network cost parameters. The wasm is not parsed: the
+    // estimate applies the same cost model as `contract_code_memory_size_for_rent`
+    // does to a contract code entry without refined cost inputs, i.e. it only
+    // depends on the code size, so it is approximate.
+    pub(crate) fn estimate_compile_memory(
+        &self,
+        wasm: &[u8],
+        cpu_cost_params: &CxxBuf,
+        mem_cost_params: &CxxBuf,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let contract_code_entry = ContractCodeEntry {
+            ext: xdr::ContractCodeEntryExt::V0,
+            hash: Hash(Sha256::digest(wasm).into()),
+            code: wasm.to_vec().try_into()?,
+        };
+        let budget = Budget::try_from_configs(
+            0,
+            0,
+            non_metered_xdr_from_cxx_buf::<ContractCostParams>(cpu_cost_params)?,
+            non_metered_xdr_from_cxx_buf::<ContractCostParams>(mem_cost_params)?,
+        )?;
+        Ok(super::wasm_module_memory_cost_wrapper(
+            &budget,
+            &contract_code_entry,
+        )?)
+    }
+
+    // Removes the module for `key` and returns whether there was one, unless
+    // it is pinned: then this fails if `error_if_pinned` is set and otherwise
+    // does nothing.
+    pub(crate) fn evict(
+        &mut self,
+        key: &[u8; 32],
+        error_if_pinned: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut keys = self.lock_module_keys()?;
+        if keys.pinned.contains(key) {
+            if error_if_pinned {
+                return Err(CoreHostError::General("cannot evict pinned module".into()).into());
+            }
+            return Ok(false);
+        }
+        let removed = keys.cached.remove(key).is_some();
+        let _ = self.module_cache.remove_module(&key.clone().into())?;
+        Ok(removed)
+    }
+
+    // Removes every module that isn't pinned. Modules only get into the
+    // underlying cache through `compile`, which records their keys, so
+    // removing the recorded unpinned keys leaves nothing else behind.
     pub(crate) fn clear(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        Ok(self.module_cache.clear()?)
+        let mut keys = self.lock_module_keys()?;
+        if keys.pinned.is_empty() {
+            keys.cached.clear();
+            return Ok(self.module_cache.clear()?);
+        }
+        let ModuleKeys { cached, pinned } = &mut *keys;
+        for key in cached.keys().filter(|key| !pinned.contains(*key)) {
+            self.module_cache.remove_module(&(*key).into())?;
+        }
+        cached.retain(|key, _| pinned.contains(key));
+        Ok(())
+    }
+
+    pub(crate) fn pin_module(&mut self, key: &[u8; 32]) -> Result<(), Box<dyn std::error::Error>> {
+        self.lock_module_keys()?.pinned.insert(*key);
+        Ok(())
+    }
+
+    pub(crate) fn unpin_module(
+        &mut self,
+        key: &[u8; 32],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.lock_module_keys()?.pinned.remove(key);
+        Ok(())
+    }
+
+    // Returns the keys of all modules `compile` has added (and that have not
+    // been evicted since), in ascending order.
+    pub(crate) fn export_cached_keys(&self) -> Result<Vec<[u8; 32]>, Box<dyn std::error::Error>> {
+        let mut keys: Vec<[u8; 32]> = self.lock_module_keys()?.cached.keys().copied().collect();
+        keys.sort_unstable();
+        Ok(keys)
+    }
+
+    // Returns the protocol version the module for `key` was compiled for, or
+    // None if `compile` hasn't cached it.
+    pub(crate) fn module_protocol_version(
+        &self,
+        key: &[u8; 32],
+    ) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        Ok(self.lock_module_keys()?.cached.get(key).copied())
     }
 
     pub(crate) fn contains_module(
@@ -770,6 +2996,620 @@ impl ProtocolSpecificModuleCache {
     pub(crate) fn shallow_clone(&self) -> Result<Self, Box<dyn std::error::Error>> {
         let mut new = Self::new()?;
         new.module_cache = self.module_cache.clone();
+        new.module_keys = self.module_keys.clone();
         Ok(new)
     }
 }
+
+#[test]
+fn extract_ledger_effects_is_independent_of_change_order() {
+    let make_change = |hash: u8, value: u8| LedgerEntryChange {
+        encoded_key: non_metered_xdr_to_vec(&LedgerKey::ContractCode(xdr::LedgerKeyContractCode {
+            hash: Hash([hash; 32]),
+        }))
+        .unwrap(),
+        encoded_new_value: Some(vec![value]),
+        ..Default::default()
+    };
+    let forward = extract_ledger_effects(
+        vec![make_change(1, 10), make_change(2, 20)],
+        &HashSet::new(),
+        |_| true,
+    )
+    .unwrap();
+    let backward = extract_ledger_effects(
+        vec![make_change(2, 20), make_change(1, 10)],
+        &HashSet::new(),
+        |_| true,
+    )
+    .unwrap();
+    let forward: Vec<Vec<u8>> = forward
+        .modified_entries
+        .into_iter()
+        .map(|buf| buf.data)
+        .collect();
+    let backward: Vec<Vec<u8>> = backward
+        .modified_entries
+        .into_iter()
+        .map(|buf| buf.data)
+        .collect();
+    assert_eq!(forward, vec![vec![10], vec![20]]);
+    assert_eq!(forward, backward);
+}
+
+#[test]
+fn unchanged_restored_entries_are_still_returned() {
+    let make_change = |hash: u8, value: u8| LedgerEntryChange {
+        encoded_key: non_metered_xdr_to_vec(&LedgerKey::ContractCode(xdr::LedgerKeyContractCode {
+            hash: Hash([hash; 32]),
+        }))
+        .unwrap(),
+        encoded_new_value: Some(vec![value]),
+        ..Default::default()
+    };
+    // Both entries are written back unchanged, but only the first one was
+    // live: the second one was restored.
+    let unchanged: HashSet<&[u8]> = [&[10u8][..], &[20u8][..]].into();
+    let effects = extract_ledger_effects(
+        vec![make_change(1, 10), make_change(2, 20)],
+        &unchanged,
+        |change| change.encoded_new_value.as_deref() == Some(&[10u8][..]),
+    )
+    .unwrap();
+    let modified: Vec<Vec<u8>> = effects
+        .modified_entries
+        .into_iter()
+        .map(|buf| buf.data)
+        .collect();
+    assert_eq!(modified, vec![vec![20]]);
+    assert!(!effects.read_only);
+}
+
+#[test]
+fn entries_without_an_old_size_are_live_only_if_passed_in_and_not_restored() {
+    let ttl_entry = |hash: u8| {
+        non_metered_xdr_to_vec(&TtlEntry {
+            key_hash: Hash([hash; 32]),
+            live_until_ledger_seq: 100,
+        })
+        .unwrap()
+    };
+    let ttl_entries = [ttl_entry(1), ttl_entry(2), vec![]];
+    let input = input_ttl_key_hashes(ttl_entries.iter().map(|buf| buf.as_slice()));
+    assert_eq!(input, [&[1u8; 32][..], &[2u8; 32][..]].into());
+
+    let change = |hash: u8| LedgerEntryChange {
+        ttl_change: Some(super::contract_data_ttl_change(vec![hash; 32], 0, 100)),
+        ..Default::default()
+    };
+    let is_input = |key_hash: &[u8]| input.contains(key_hash);
+    // Passed in but expired.
+    assert!(passed_in_live(&change(1), false, is_input));
+    // Restored.
+    assert!(!passed_in_live(&change(1), true, is_input));
+    // New.
+    assert!(!passed_in_live(&change(3), false, is_input));
+    assert!(!passed_in_live(
+        &LedgerEntryChange::default(),
+        false,
+        is_input
+    ));
+}
+
+#[test]
+fn itemized_rent_fees_add_up_to_aggregate_rent_fee() {
+    let fee_config = || CxxRentFeeConfiguration {
+        fee_per_write_1kb: 1000,
+        fee_per_rent_1kb: 1000,
+        fee_per_write_entry: 100,
+        persistent_rent_rate_denominator: 10,
+        temporary_rent_rate_denominator: 100,
+    };
+    let entry =
+        |is_persistent: bool, size: u32, old_ttl: u32, new_ttl: u32| CxxLedgerEntryRentChange {
+            is_persistent,
+            is_code_entry: false,
+            old_size_bytes: size,
+            new_size_bytes: size,
+            old_live_until_ledger: old_ttl,
+            new_live_until_ledger: new_ttl,
+        };
+    let entries = vec![
+        entry(true, 100, 1000, 50_000),
+        entry(false, 2000, 0, 20_000),
+        entry(true, 500, 3000, 3000),
+    ];
+    let itemized = compute_rent_fee_itemized(&entries, fee_config(), 100);
+    let aggregate = compute_rent_fee(&entries, fee_config(), 100);
+    assert_eq!(itemized.len(), entries.len());
+    // Writing the TTL extensions is charged per 1kb increment, which is
+    // rounded up once per entry when itemized, and once overall otherwise.
+    let extended = entries
+        .iter()
+        .filter(|e| e.old_live_until_ledger < e.new_live_until_ledger)
+        .count() as i64;
+    let sum: i64 = itemized.iter().sum();
+    assert!(aggregate <= sum && sum < aggregate + extended);
+}
+
+#[test]
+fn budget_delta_only_counts_charges_after_checkpoint() {
+    // Tests attribute cost to one sub-call of a composite contract by
+    // snapshotting the consumed cpu and memory before it and subtracting
+    // the snapshot afterwards.
+    let budget = Budget::default();
+    let consumed = || {
+        (
+            budget.get_cpu_insns_consumed().unwrap(),
+            budget.get_mem_bytes_consumed().unwrap(),
+        )
+    };
+    budget
+        .charge(xdr::ContractCostType::VmInstantiation, Some(1000))
+        .unwrap();
+    let checkpoint = consumed();
+    budget
+        .charge(xdr::ContractCostType::VmInstantiation, Some(1000))
+        .unwrap();
+    let (cpu, mem) = consumed();
+    assert_eq!(cpu - checkpoint.0, checkpoint.0);
+    assert_eq!(mem - checkpoint.1, checkpoint.1);
+}
+
+#[cfg(feature = "testutils")]
+#[test]
+fn transaction_resource_fee_rounds_up_from_exact_fee() {
+    let fee_config = || CxxFeeConfiguration {
+        fee_per_instruction_increment: 25,
+        fee_per_disk_read_entry: 6250,
+        fee_per_write_entry: 10000,
+        fee_per_disk_read_1kb: 1786,
+        fee_per_write_1kb: 11800,
+        fee_per_historical_1kb: 16235,
+        fee_per_contract_event_1kb: 10000,
+        fee_per_transaction_size_1kb: 1624,
+    };
+    let resources = || CxxTransactionResources {
+        instructions: 1_234_567,
+        disk_read_entries: 3,
+        write_entries: 2,
+        disk_read_bytes: 5_001,
+        write_bytes: 777,
+        contract_events_size_bytes: 333,
+        transaction_size_bytes: 1_111,
+    };
+    let fee = compute_transaction_resource_fee(resources(), fee_config());
+    let exact = compute_transaction_resource_fee_exact(resources(), fee_config());
+    // Each of the (at most 6) per-increment fees is rounded up by less than one
+    // stroop, and the entry fees are not rounded at all.
+    for (rounded, exact) in [
+        (fee.non_refundable_fee, exact.non_refundable_fee),
+        (fee.refundable_fee, exact.refundable_fee),
+    ] {
+        assert!(rounded * EXACT_FEE_SCALE >= exact);
+        assert!(rounded * EXACT_FEE_SCALE - exact < 6 * EXACT_FEE_SCALE);
+    }
+    assert_ne!(
+        fee.non_refundable_fee * EXACT_FEE_SCALE,
+        exact.non_refundable_fee
+    );
+}
+
+#[test]
+fn vm_instantiation_is_charged_from_cost_inputs_when_present() {
+    let entry = |ext| ContractCodeEntry {
+        ext,
+        hash: Hash([0; 32]),
+        code: vec![0u8; 100_000].try_into().unwrap(),
+    };
+    let consumed = |entry: &ContractCodeEntry| {
+        let budget = Budget::default();
+        charge_vm_instantiation(&budget, entry).unwrap();
+        (
+            budget.get_cpu_insns_consumed().unwrap(),
+            budget.get_mem_bytes_consumed().unwrap(),
+        )
+    };
+    let legacy = consumed(&entry(xdr::ContractCodeEntryExt::V0));
+    let refined = consumed(&entry(xdr::ContractCodeEntryExt::V1(
+        xdr::ContractCodeEntryV1 {
+            ext: ExtensionPoint::V0,
+            cost_inputs: xdr::ContractCodeCostInputs {
+                ext: ExtensionPoint::V0,
+                n_instructions: 10,
+                n_functions: 1,
+                n_globals: 0,
+                n_table_entries: 0,
+                n_types: 1,
+                n_data_segments: 0,
+                n_elem_segments: 0,
+                n_imports: 0,
+                n_exports: 1,
+                n_data_segment_bytes: 0,
+            },
+        },
+    )));
+    // A tiny module padded to a large code size is cheap to instantiate,
+    // which only the refined cost inputs can tell.
+    assert!(refined.0 < legacy.0);
+    assert!(refined.1 < legacy.1);
+}
+
+#[test]
+fn restore_fee_is_write_fee_plus_rent_fee() {
+    let rent_config = || CxxRentFeeConfiguration {
+        fee_per_write_1kb: 1000,
+        fee_per_rent_1kb: 1000,
+        fee_per_write_entry: 100,
+        persistent_rent_rate_denominator: 10,
+        temporary_rent_rate_denominator: 100,
+    };
+    let restored = |is_code_entry: bool, size: u32| CxxLedgerEntryRentChange {
+        is_persistent: true,
+        is_code_entry,
+        old_size_bytes: 0,
+        new_size_bytes: size,
+        old_live_until_ledger: 0,
+        new_live_until_ledger: 5000,
+    };
+    let rent_changes = || vec![restored(false, 300), restored(true, 2000)];
+    let fee = restore_fee_for_rent_changes(rent_changes(), 1500, rent_config(), 100);
+    // 2 entries at 100 each, and 1500 bytes at 1000 per 1kb rounded up.
+    let write_fee = 2 * 100 + (1500 * 1000 + 1023) / 1024;
+    let rent_fee = compute_rent_fee(&rent_changes(), rent_config(), 100);
+    assert!(rent_fee > 0);
+    assert_eq!(fee, write_fee + rent_fee);
+}
+
+#[test]
+fn restore_writes_are_charged_the_rent_rate_before_protocol_23() {
+    // As core passes it: no flat write rate before protocol 23.
+    let rent_config = || CxxRentFeeConfiguration {
+        fee_per_write_1kb: 0,
+        fee_per_rent_1kb: 0,
+        fee_per_write_entry: 100,
+        persistent_rent_rate_denominator: 10,
+        temporary_rent_rate_denominator: 100,
+    };
+    let pre_23 = restore_rent_fee_config(22, 3000, rent_config());
+    assert_eq!(pre_23.fee_per_rent_1kb, 3000);
+    assert_eq!(pre_23.fee_per_write_1kb, 3000);
+    let from_23 = restore_rent_fee_config(23, 3000, rent_config());
+    assert_eq!(from_23.fee_per_rent_1kb, 3000);
+    assert_eq!(from_23.fee_per_write_1kb, 0);
+
+    let restored = || CxxLedgerEntryRentChange {
+        is_persistent: true,
+        is_code_entry: false,
+        old_size_bytes: 0,
+        new_size_bytes: 1024,
+        old_live_until_ledger: 0,
+        new_live_until_ledger: 5000,
+    };
+    let write_fee = |config: CxxRentFeeConfiguration| {
+        let rent_fee = compute_rent_fee(&[restored()], config.clone(), 100);
+        restore_fee_for_rent_changes(vec![restored()], 1024, config, 100) - rent_fee
+    };
+    // Only the pre-23 write fee includes the 1kb written at the rent rate.
+    assert_eq!(write_fee(pre_23), 100 + 3000);
+    assert_eq!(write_fee(from_23), 100);
+}
+
+#[cfg(feature = "testutils")]
+#[test]
+fn invocation_inputs_round_trip_through_a_bundle() {
+    let buf = |byte: u8, len: usize| RustBuf::from(vec![byte; len]);
+    let inputs = || crate::InvocationInputsBundle {
+        instruction_limit: 1,
+        hf: buf(2, 3),
+        resources: buf(3, 4),
+        restored_rw_entry_indices: vec![0, 5, 6],
+        source_account: buf(4, 5),
+        auth_entries: vec![buf(5, 1), buf(6, 2)],
+        protocol_version: 7,
+        sequence_number: 8,
+        timestamp: 9,
+        network_id: vec![10; 32],
+        base_reserve: 11,
+        memory_limit: 12,
+        min_temp_entry_ttl: 13,
+        min_persistent_entry_ttl: 14,
+        max_entry_ttl: 15,
+        cpu_cost_params: buf(16, 6),
+        mem_cost_params: buf(17, 7),
+        ledger_entries: vec![buf(18, 8), buf(19, 9), buf(20, 0)],
+        ttl_entries: vec![buf(21, 10)],
+        base_prng_seed: buf(22, 32),
+        rent_fee_configuration: CxxRentFeeConfiguration {
+            fee_per_write_1kb: 23,
+            fee_per_rent_1kb: 24,
+            fee_per_write_entry: 25,
+            persistent_rent_rate_denominator: 26,
+            temporary_rent_rate_denominator: 27,
+        },
+    };
+    let encoded = encode_invocation_inputs(&inputs()).unwrap();
+    let (expected, actual) = (inputs(), decode_invocation_inputs(&encoded.data).unwrap());
+    let data = |buf: &RustBuf| buf.data.clone();
+    let datas = |bufs: &Vec<RustBuf>| bufs.iter().map(data).collect::<Vec<_>>();
+    assert_eq!(actual.instruction_limit, expected.instruction_limit);
+    assert_eq!(data(&actual.hf), data(&expected.hf));
+    assert_eq!(data(&actual.resources), data(&expected.resources));
+    assert_eq!(
+        actual.restored_rw_entry_indices,
+        expected.restored_rw_entry_indices
+    );
+    assert_eq!(data(&actual.source_account), data(&expected.source_account));
+    assert_eq!(datas(&actual.auth_entries), datas(&expected.auth_entries));
+    assert_eq!(actual.protocol_version, expected.protocol_version);
+    assert_eq!(actual.sequence_number, expected.sequence_number);
+    assert_eq!(actual.timestamp, expected.timestamp);
+    assert_eq!(actual.network_id, expected.network_id);
+    assert_eq!(actual.base_reserve, expected.base_reserve);
+    assert_eq!(actual.memory_limit, expected.memory_limit);
+    assert_eq!(actual.min_temp_entry_ttl, expected.min_temp_entry_ttl);
+    assert_eq!(
+        actual.min_persistent_entry_ttl,
+        expected.min_persistent_entry_ttl
+    );
+    assert_eq!(actual.max_entry_ttl, expected.max_entry_ttl);
+    assert_eq!(
+        data(&actual.cpu_cost_params),
+        data(&expected.cpu_cost_params)
+    );
+    assert_eq!(
+        data(&actual.mem_cost_params),
+        data(&expected.mem_cost_params)
+    );
+    assert_eq!(
+        datas(&actual.ledger_entries),
+        datas(&expected.ledger_entries)
+    );
+    assert_eq!(datas(&actual.ttl_entries), datas(&expected.ttl_entries));
+    assert_eq!(data(&actual.base_prng_seed), data(&expected.base_prng_seed));
+    assert_eq!(
+        format!("{:?}", actual.rent_fee_configuration),
+        format!("{:?}", expected.rent_fee_configuration)
+    );
+}
+
+#[test]
+fn restored_indices_must_be_increasing() {
+    assert!(validate_restored_indices(&[]).is_ok());
+    assert!(validate_restored_indices(&[0, 2, 3]).is_ok());
+    assert!(validate_restored_indices(&[1, 1]).is_err());
+    assert!(validate_restored_indices(&[2, 1]).is_err());
+}
+
+#[test]
+fn pack_buffers_length_prefixes_each_buffer() {
+    let packed = pack_buffers(vec![vec![7u8, 8].into(), vec![].into(), vec![9u8].into()]);
+    assert_eq!(
+        packed.data,
+        [
+            &[0u8, 0, 0, 0, 0, 0, 0, 2, 7, 8][..],
+            &[0u8, 0, 0, 0, 0, 0, 0, 0][..],
+            &[0u8, 0, 0, 0, 0, 0, 0, 1, 9][..],
+        ]
+        .concat()
+    );
+    assert!(pack_buffers(vec![]).data.is_empty());
+}
+
+#[test]
+fn packed_buffers_unpack_to_the_original_buffers() {
+    use crate::soroban_proto_all::protocol_agnostic::unpack_buffers;
+    let bufs: Vec<Vec<u8>> = vec![vec![7, 8], vec![], vec![9; 300]];
+    let packed = pack_buffers(bufs.iter().map(|buf| buf.clone().into()).collect());
+    let unpacked: Vec<Vec<u8>> = unpack_buffers(&packed.data)
+        .unwrap()
+        .into_iter()
+        .map(|buf| buf.to_vec())
+        .collect();
+    assert_eq!(unpacked, bufs);
+    assert!(unpack_buffers(&[]).unwrap().is_empty());
+    assert!(unpack_buffers(&packed.data[..packed.data.len() - 1]).is_err());
+    assert!(unpack_buffers(&packed.data[..4]).is_err());
+}
+
+#[cfg(feature = "testutils")]
+#[test]
+fn cost_param_diff_reports_changed_added_and_removed_entries() {
+    let entry = |const_term, linear_term| xdr::ContractCostParamEntry {
+        ext: ExtensionPoint::V0,
+        const_term,
+        linear_term,
+    };
+    let old = ContractCostParams(vec![entry(10, 1), entry(20, 2)].try_into().unwrap());
+    let changed = ContractCostParams(
+        vec![entry(10, 1), entry(25, 2), entry(30, 3)]
+            .try_into()
+            .unwrap(),
+    );
+    assert!(diff_decoded_cost_params(&old, &old).is_empty());
+    assert_eq!(
+        diff_decoded_cost_params(&old, &changed),
+        vec![
+            format!(
+                "{}: const 20->25, linear 2->2",
+                xdr::ContractCostType::VARIANTS_STR[1]
+            ),
+            format!(
+                "{}: added (const 30, linear 3)",
+                xdr::ContractCostType::VARIANTS_STR[2]
+            ),
+        ]
+    );
+    assert_eq!(
+        diff_decoded_cost_params(&changed, &old),
+        vec![
+            format!(
+                "{}: const 25->20, linear 2->2",
+                xdr::ContractCostType::VARIANTS_STR[1]
+            ),
+            format!(
+                "{}: removed (was const 30, linear 3)",
+                xdr::ContractCostType::VARIANTS_STR[2]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn diagnostic_event_encoding_stops_at_output_limit() {
+    let events: Vec<DiagnosticEvent> = (0..4)
+        .map(|_| make_failure_diagnostic_event(None, vec![ScVal::U32(7)]))
+        .collect();
+    let event_len = non_metered_xdr_to_rust_buf(&events[0]).unwrap().data.len();
+
+    let mut unbounded = OutputSizeLimit::new(0);
+    assert_eq!(
+        encode_diagnostic_events_within(&events, &mut unbounded).len(),
+        4
+    );
+    assert!(!unbounded.exceeded());
+
+    // The second event crosses the limit, and nothing after it is encoded.
+    let mut limited = OutputSizeLimit::new(event_len as u64 + 1);
+    assert_eq!(
+        encode_diagnostic_events_within(&events, &mut limited).len(),
+        2
+    );
+    assert!(limited.exceeded());
+}
+
+#[test]
+fn diagnostic_events_are_grouped_by_contract_in_order() {
+    let encode = |contract_id: Option<[u8; 32]>, topic: u32| {
+        non_metered_xdr_to_rust_buf(&make_failure_diagnostic_event(
+            contract_id,
+            vec![ScVal::U32(topic)],
+        ))
+        .unwrap()
+    };
+    let events = vec![
+        encode(Some([1; 32]), 0),
+        encode(None, 1),
+        encode(Some([2; 32]), 2),
+        encode(Some([1; 32]), 3),
+    ];
+    let groups = group_diagnostic_events_by_contract(&events).unwrap();
+    let summary: Vec<([u8; 32], Vec<Vec<u8>>)> = groups
+        .into_iter()
+        .map(|(id, group)| (id, group.into_iter().map(|buf| buf.data).collect()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                [1; 32],
+                vec![events[0].data.clone(), events[3].data.clone()]
+            ),
+            (NO_CONTRACT_ID, vec![events[1].data.clone()]),
+            ([2; 32], vec![events[2].data.clone()]),
+        ]
+    );
+}
+
+#[test]
+fn expired_and_new_entries_are_labeled_by_input_existence() {
+    let encoded_key = |hash: u8| {
+        non_metered_xdr_to_vec(&LedgerKey::ContractData(xdr::LedgerKeyContractData {
+            #[allow(clippy::useless_conversion)]
+            contract: xdr::ScAddress::Contract(Hash([hash; 32]).into()),
+            key: ScVal::U32(0),
+            durability: xdr::ContractDataDurability::Persistent,
+        }))
+        .unwrap()
+    };
+    // The host reports all three entries without an old entry: the first
+    // two were passed in but expired, the third one is new.
+    let make_change = |hash: u8, new_value: Option<Vec<u8>>| LedgerEntryChange {
+        encoded_key: encoded_key(hash),
+        encoded_new_value: new_value,
+        ttl_change: Some(super::contract_data_ttl_change(vec![hash; 32], 0, 100)),
+        ..Default::default()
+    };
+    let changes = vec![
+        make_change(1, Some(vec![10])),
+        make_change(2, None),
+        make_change(3, Some(vec![30])),
+    ];
+    let passed_in: HashSet<Vec<u8>> = [encoded_key(1), encoded_key(2)].into();
+    let effects = extract_ledger_effects(changes, &HashSet::new(), |change| {
+        passed_in.contains(&change.encoded_key)
+    })
+    .unwrap();
+
+    let deleted: Vec<Vec<u8>> = effects
+        .deleted_keys
+        .into_iter()
+        .map(|buf| buf.data)
+        .collect();
+    assert_eq!(deleted, vec![encoded_key(2)]);
+    // Shared enums only compare by their `repr`.
+    let mut kinds: Vec<(Vec<u8>, u8)> = effects
+        .modified_entries
+        .into_iter()
+        .map(|buf| buf.data)
+        .zip(effects.modified_entry_kinds.iter().map(|kind| kind.repr))
+        .collect();
+    kinds.sort_by(|a, b| a.0.cmp(&b.0));
+    let ttl_entry = |hash: u8| {
+        non_metered_xdr_to_vec(&LedgerEntry {
+            last_modified_ledger_seq: 0,
+            data: LedgerEntryData::Ttl(TtlEntry {
+                key_hash: Hash([hash; 32]),
+                live_until_ledger_seq: 100,
+            }),
+            ext: LedgerEntryExt::V0,
+        })
+        .unwrap()
+    };
+    let mut expected = vec![
+        (vec![10], LedgerEntryChangeKind::Updated),
+        (vec![30], LedgerEntryChangeKind::Created),
+        (ttl_entry(1), LedgerEntryChangeKind::TtlBumped),
+        (ttl_entry(2), LedgerEntryChangeKind::TtlBumped),
+        (ttl_entry(3), LedgerEntryChangeKind::Created),
+    ];
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    let expected: Vec<(Vec<u8>, u8)> = expected
+        .into_iter()
+        .map(|(entry, kind)| (entry, kind.repr))
+        .collect();
+    assert_eq!(kinds, expected);
+}
+
+#[test]
+fn host_errors_are_classified_by_type_code_and_protocol() {
+    let classify = |ty, code, protocol_version| {
+        classify_host_error(&HostError::from((ty, code)), protocol_version).repr
+    };
+    assert_eq!(
+        classify(ScErrorType::Contract, ScErrorCode::InternalError, 21),
+        HostErrorClass::InternalFault.repr
+    );
+    assert_eq!(
+        classify(ScErrorType::Contract, ScErrorCode::InternalError, 22),
+        HostErrorClass::ContractFault.repr
+    );
+    assert_eq!(
+        classify(ScErrorType::Storage, ScErrorCode::InternalError, 22),
+        HostErrorClass::InternalFault.repr
+    );
+    assert_eq!(
+        classify(ScErrorType::Budget, ScErrorCode::ExceededLimit, 22),
+        HostErrorClass::BudgetExhausted.repr
+    );
+    assert_eq!(
+        classify(ScErrorType::Value, ScErrorCode::InvalidInput, 22),
+        HostErrorClass::MalformedInput.repr
+    );
+    // Storage errors, e.g. accesses outside the footprint, fail the same way
+    // when retried.
+    assert_eq!(
+        classify(ScErrorType::Storage, ScErrorCode::ExceededLimit, 22),
+        HostErrorClass::ContractFault.repr
+    );
+}
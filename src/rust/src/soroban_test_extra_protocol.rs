@@ -9,8 +9,8 @@ use std::str::FromStr;
 use crate::{
     log::partition::TX,
     soroban_proto_all::{get_host_module_for_protocol, p22, HostModule},
-    CxxBuf, CxxLedgerInfo, CxxRentFeeConfiguration, InvokeHostFunctionOutput, RustBuf,
-    SorobanModuleCache,
+    CxxBuf, CxxInvokeHostFunctionOptions, CxxLedgerInfo, CxxRentFeeConfiguration,
+    InvokeHostFunctionOutput, RustBuf, SorobanModuleCache,
 };
 use log::{info, warn};
 
@@ -22,14 +22,15 @@ pub(super) fn maybe_invoke_host_function_again_and_compare_outputs(
     mut instruction_limit: u32,
     hf_buf: &CxxBuf,
     mut resources_buf: CxxBuf,
-    restored_rw_entry_indices: &Vec<u32>,
+    restored_rw_entry_indices: &[u32],
     source_account_buf: &CxxBuf,
-    auth_entries: &Vec<CxxBuf>,
+    auth_entries: &[CxxBuf],
     mut ledger_info: CxxLedgerInfo,
-    ledger_entries: &Vec<CxxBuf>,
-    ttl_entries: &Vec<CxxBuf>,
+    ledger_entries: &[CxxBuf],
+    ttl_entries: &[CxxBuf],
     base_prng_seed: &CxxBuf,
     rent_fee_configuration: CxxRentFeeConfiguration,
+    options: &CxxInvokeHostFunctionOptions,
     module_cache: &SorobanModuleCache,
 ) {
     if let Ok(extra) = std::env::var("SOROBAN_TEST_EXTRA_PROTOCOL") {
@@ -62,6 +63,7 @@ pub(super) fn maybe_invoke_host_function_again_and_compare_outputs(
                     ttl_entries,
                     base_prng_seed,
                     &rent_fee_configuration,
+                    options,
                     module_cache,
                 );
                 if mostly_the_same_host_function_output(&res1, &res2) {
@@ -526,7 +526,8 @@ class InvokeHostFunctionApplyHelper : virtual LedgerAccessHelper
                 toCxxBuf(mOpFrame.getSourceID()), authEntryCxxBufs,
                 getLedgerInfo(), mLedgerEntryCxxBufs, mTtlEntryCxxBufs,
                 basePrngSeedBuf,
-                mSorobanConfig.rustBridgeRentFeeConfiguration(), *moduleCache);
+                mSorobanConfig.rustBridgeRentFeeConfiguration(),
+                CxxInvokeHostFunctionOptions{}, *moduleCache);
             mMetrics.mCpuInsn = out.cpu_insns;
             mMetrics.mMemByte = out.mem_bytes;
             mMetrics.mInvokeTimeNsecs = out.time_nsecs;